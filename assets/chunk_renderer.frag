@@ -1,17 +1,32 @@
 #version 400 core
 
 uniform sampler2DArray texture_atlas;
+// 0 for opaque, 1 for cutout and 2 for translucent faces.
+uniform int render_layer;
 
 in vec2 fs_tex_pos;
-flat in uint fs_blk_type;
+flat in uint fs_tex_layer;
+// Sky and block light from 0 to 1.
+in vec2 fs_light;
+// Ambient occlusion from 0 for a fully occluded corner to 1 for an open one.
+in float fs_ao;
 
 out vec4 color;
 
+// Each light level is 80% as bright as the one above it.
+float brightness(float level) {
+  return pow(0.8, 15.0*(1.0 - level));
+}
+
 void main() {
-  if (fs_blk_type == 0)
-  {
+  vec4 albedo = texture(texture_atlas, vec3(fs_tex_pos, float(fs_tex_layer)));
+  if (render_layer == 1 && albedo.a < 0.5) {
     discard;
   }
-
-  color = texture(texture_atlas, vec3(fs_tex_pos, float(fs_blk_type - 1)));
+  vec3 sky = vec3(brightness(fs_light.x));
+  // Block light is a little warmer than sky light.
+  vec3 block = brightness(fs_light.y)*vec3(1.0, 0.9, 0.75);
+  vec3 light = max(max(sky, block), vec3(0.03))*mix(0.45, 1.0, fs_ao);
+  // Only translucent faces are blended, the others are fully opaque.
+  color = vec4(albedo.rgb*light, render_layer == 2 ? albedo.a : 1.0);
 }
@@ -1,31 +1,23 @@
 #version 400 core
 
 uniform mat4 pos_from_wld_to_clp_space;
+uniform mat4 pos_from_chk_to_wld_space;
 
 in vec3 vs_ver_pos;
 in vec2 vs_tex_pos;
-in uint vs_blk_type;
+in uint vs_tex_layer;
+in vec2 vs_light;
+in float vs_ao;
 
 out vec2 fs_tex_pos;
-flat out uint fs_blk_type;
+flat out uint fs_tex_layer;
+out vec2 fs_light;
+out float fs_ao;
 
 void main() {
-  uvec3 blk_pos = uvec3(
-    (gl_InstanceID >> 0) & 0xF,
-    (gl_InstanceID >> 4) & 0xF,
-    (gl_InstanceID >> 8) & 0xF
-  );
-
-  vec3 blk_pos_f = vec3(blk_pos);
-
-  mat4 pos_from_obj_to_wld_space = mat4(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 1.0, 0.0,
-    blk_pos_f.x, blk_pos_f.y, blk_pos_f.z, 1.0
-  );
-
-  gl_Position = pos_from_wld_to_clp_space*pos_from_obj_to_wld_space*vec4(vs_ver_pos, 1.0);
+  gl_Position = pos_from_wld_to_clp_space*pos_from_chk_to_wld_space*vec4(vs_ver_pos, 1.0);
   fs_tex_pos = vs_tex_pos;
-  fs_blk_type = vs_blk_type;
+  fs_tex_layer = vs_tex_layer;
+  fs_light = vs_light;
+  fs_ao = vs_ao;
 }
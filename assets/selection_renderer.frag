@@ -0,0 +1,7 @@
+#version 400 core
+
+out vec4 color;
+
+void main() {
+  color = vec4(0.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,9 @@
+#version 400 core
+
+uniform mat4 pos_from_obj_to_clp_space;
+
+in vec3 vs_ver_pos;
+
+void main() {
+  gl_Position = pos_from_obj_to_clp_space*vec4(vs_ver_pos, 1.0);
+}
@@ -9,8 +9,9 @@ pub struct Assets {
     pub chunk_renderer_frag: PathBuf,
     pub text_renderer_vert: PathBuf,
     pub text_renderer_frag: PathBuf,
-    pub dirt_xyz_png: PathBuf,
-    pub stone_xyz_png: PathBuf,
+    pub selection_renderer_vert: PathBuf,
+    pub selection_renderer_frag: PathBuf,
+    pub blocks_txt: PathBuf,
     pub font_padded_sdf_png: PathBuf,
 }
 
@@ -36,10 +37,13 @@ impl Assets {
         let text_renderer_frag = [root.as_path(), Path::new("text_renderer.frag")]
             .iter()
             .collect();
-        let dirt_xyz_png = [root.as_path(), Path::new("dirt_xyz.png")].iter().collect();
-        let stone_xyz_png = [root.as_path(), Path::new("stone_xyz.png")]
+        let selection_renderer_vert = [root.as_path(), Path::new("selection_renderer.vert")]
             .iter()
             .collect();
+        let selection_renderer_frag = [root.as_path(), Path::new("selection_renderer.frag")]
+            .iter()
+            .collect();
+        let blocks_txt = [root.as_path(), Path::new("blocks.txt")].iter().collect();
         let font_padded_sdf_png = [root.as_path(), Path::new("font-padded-sdf.png")]
             .iter()
             .collect();
@@ -52,11 +56,17 @@ impl Assets {
             chunk_renderer_frag,
             text_renderer_vert,
             text_renderer_frag,
-            dirt_xyz_png,
-            stone_xyz_png,
+            selection_renderer_vert,
+            selection_renderer_frag,
+            blocks_txt,
             font_padded_sdf_png,
         }
     }
+
+    /// Path of a block texture named in the block definitions.
+    pub fn block_texture_path(&self, name: &str) -> PathBuf {
+        [self.root.as_path(), Path::new(name)].iter().collect()
+    }
 }
 
 pub fn file_to_string<P: AsRef<Path>>(path: P) -> ::std::io::Result<String> {
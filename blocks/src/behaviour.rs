@@ -0,0 +1,187 @@
+use block::Block;
+use block_registry::BlockRegistry;
+use cgmath::*;
+use falling::FallingBlock;
+use tick::BlockTicks;
+use tick::TickContext;
+use world::split_block_position;
+use world::World;
+
+/// Sky or block light needed above dirt for grass to spread onto it.
+const GRASS_SPREAD_LIGHT: u8 = 9;
+
+/// Gives the block types that have behaviour over time their tick
+/// callbacks. Block types that are not in the registry are skipped.
+pub fn register(registry: &mut BlockRegistry) {
+    if let Some(grass) = registry.block("grass") {
+        registry.set_ticks(
+            grass,
+            BlockTicks {
+                random: Some(grass_random_tick),
+                scheduled: None,
+            },
+        );
+    }
+
+    let falling: Vec<Block> = registry.blocks().filter(|&b| registry.falls(b)).collect();
+    for block in falling {
+        let mut ticks = registry.ticks(block);
+        ticks.scheduled = Some(start_falling);
+        registry.set_ticks(block, ticks);
+    }
+}
+
+/// Blocks that fall turn into a falling block when a neighbour changes
+/// and there is nothing solid under them.
+fn start_falling(context: &mut TickContext, position: Vector3<i32>) {
+    let below = position - Vector3::unit_y();
+    if context
+        .world
+        .chunk_at(split_block_position(below).0)
+        .is_none()
+        || context.registry.is_solid(context.world.block_at(below))
+    {
+        return;
+    }
+    let block = context.world.block_at(position);
+    context.world.set_block(position, Block::VOID);
+    context
+        .world
+        .add_falling_block(FallingBlock::new(block, position));
+    context.block_changed(position);
+}
+
+/// Grass can not grow under opaque blocks or fluids.
+fn is_uncovered(world: &World, registry: &BlockRegistry, position: Vector3<i32>) -> bool {
+    let above = world.block_at(position + Vector3::unit_y());
+    !registry.is_opaque(above) && registry.fluid(above).is_none()
+}
+
+/// Grass turns into dirt when covered and otherwise spreads to lit dirt
+/// up to one block away horizontally, one block up and three blocks down.
+fn grass_random_tick(context: &mut TickContext, position: Vector3<i32>) {
+    let registry = context.registry;
+    let (grass, dirt) = match (registry.block("grass"), registry.block("dirt")) {
+        (Some(grass), Some(dirt)) => (grass, dirt),
+        _ => return,
+    };
+    if !is_uncovered(context.world, registry, position) {
+        context.world.set_block(position, dirt);
+        return;
+    }
+    for _ in 0..4 {
+        let target = position
+            + Vector3::new(
+                context.random(3) as i32 - 1,
+                context.random(5) as i32 - 3,
+                context.random(3) as i32 - 1,
+            );
+        let lit = context
+            .world
+            .light_at(target + Vector3::unit_y())
+            .map_or(false, |light| {
+                light.sky().max(light.block()) >= GRASS_SPREAD_LIGHT
+            });
+        if context.world.block_at(target) == dirt
+            && lit
+            && is_uncovered(context.world, registry, target)
+        {
+            context.world.set_block(target, grass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::Chunk;
+    use falling;
+    use light;
+    use tick::TickScheduler;
+
+    #[test]
+    fn grass_spreads_over_lit_dirt_and_dies_when_covered() {
+        let mut registry = BlockRegistry::parse("[stone]\n[dirt]\n[grass]", |_| true).unwrap();
+        register(&mut registry);
+        let (stone, dirt, grass) = (Block(1), Block(2), Block(3));
+
+        let mut world = World::new();
+        world.insert_chunk(Chunk::new(Vector3::zero()));
+        for z in 0..16 {
+            for x in 0..16 {
+                world.set_block(Vector3::new(x, 4, z), dirt);
+            }
+        }
+        // A roof keeps the dirt far enough under it dark.
+        for z in 0..16 {
+            for x in 4..16 {
+                world.set_block(Vector3::new(x, 8, z), stone);
+            }
+        }
+        world.set_block(Vector3::new(2, 4, 8), grass);
+        light::update(&mut world, &registry);
+
+        let mut ticks = TickScheduler::new(0);
+        for _ in 0..20_000 {
+            ticks.update(&mut world, &registry);
+            light::update(&mut world, &registry);
+        }
+        let grass_at =
+            |world: &World, x: i32, z: i32| world.block_at(Vector3::new(x, 4, z)) == grass;
+        let grass_count = |world: &World, xs: ::std::ops::Range<i32>| {
+            xs.flat_map(|x| (0..16).map(move |z| (x, z)))
+                .filter(|&(x, z)| grass_at(world, x, z))
+                .count()
+        };
+        assert!(grass_count(&world, 0..4) > 10);
+        assert_eq!(grass_count(&world, 10..16), 0);
+
+        // Covering grass turns it back into dirt.
+        world.set_block(Vector3::new(2, 5, 8), stone);
+        for _ in 0..20_000 {
+            ticks.update(&mut world, &registry);
+        }
+        assert_eq!(world.block_at(Vector3::new(2, 4, 8)), dirt);
+    }
+
+    #[test]
+    fn unsupported_blocks_fall_and_stack_up_again() {
+        let mut registry = BlockRegistry::parse("[stone]\n[sand]\nfalls = true", |_| true).unwrap();
+        register(&mut registry);
+        let (stone, sand) = (Block(1), Block(2));
+
+        let mut world = World::new();
+        world.insert_chunk(Chunk::new(Vector3::zero()));
+        world.set_block(Vector3::new(3, 0, 3), stone);
+        world.set_block(Vector3::new(3, 7, 3), stone);
+        for y in 8..11 {
+            world.set_block(Vector3::new(3, y, 3), sand);
+        }
+        let column = |world: &World, ys: ::std::ops::Range<i32>| -> Vec<Block> {
+            ys.map(|y| world.block_at(Vector3::new(3, y, 3))).collect()
+        };
+
+        let mut ticks = TickScheduler::new(0);
+        let run = |world: &mut World, ticks: &mut TickScheduler| {
+            for _ in 0..200 {
+                ticks.update(world, &registry);
+                for position in falling::update(world, &registry, 0.01) {
+                    ticks.block_changed(position);
+                }
+            }
+        };
+
+        // Supported blocks stay where they are.
+        ticks.block_changed(Vector3::new(3, 8, 3));
+        run(&mut world, &mut ticks);
+        assert_eq!(column(&world, 8..11), vec![sand; 3]);
+
+        // Removing the support drops the whole stack.
+        world.set_block(Vector3::new(3, 7, 3), Block::VOID);
+        ticks.block_changed(Vector3::new(3, 7, 3));
+        run(&mut world, &mut ticks);
+        assert!(world.falling_blocks().is_empty());
+        assert_eq!(column(&world, 1..4), vec![sand; 3]);
+        assert_eq!(column(&world, 4..11), vec![Block::VOID; 7]);
+    }
+}
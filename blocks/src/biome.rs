@@ -0,0 +1,208 @@
+use noise;
+use noise::Noise;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Biome {
+    Plains = 0,
+    Forest = 1,
+    Desert = 2,
+    Mountains = 3,
+}
+
+pub const BIOME_COUNT: usize = 4;
+
+/// How a biome shapes the terrain and what grows on it.
+#[derive(Clone, Copy, Debug)]
+pub struct BiomeParams {
+    /// Climate at which the biome is most common, each in [-1, 1].
+    pub temperature: f32,
+    pub humidity: f32,
+    /// Height around which the surface varies.
+    pub base_height: f32,
+    /// How far the surface deviates from the base height.
+    pub height_amplitude: f32,
+    /// Multiplier for the chance of placing a tree.
+    pub tree_density: f32,
+    /// Multiplier for the chance of placing a boulder.
+    pub boulder_density: f32,
+}
+
+static BIOME_PARAMS: [BiomeParams; BIOME_COUNT] = [
+    // Plains
+    BiomeParams {
+        temperature: 0.2,
+        humidity: 0.0,
+        base_height: 0.0,
+        height_amplitude: 8.0,
+        tree_density: 0.3,
+        boulder_density: 0.5,
+    },
+    // Forest
+    BiomeParams {
+        temperature: 0.0,
+        humidity: 0.6,
+        base_height: 2.0,
+        height_amplitude: 14.0,
+        tree_density: 2.5,
+        boulder_density: 0.5,
+    },
+    // Desert
+    BiomeParams {
+        temperature: 0.7,
+        humidity: -0.6,
+        base_height: -2.0,
+        height_amplitude: 6.0,
+        tree_density: 0.0,
+        boulder_density: 0.2,
+    },
+    // Mountains
+    BiomeParams {
+        temperature: -0.6,
+        humidity: 0.0,
+        base_height: 10.0,
+        height_amplitude: 40.0,
+        tree_density: 0.5,
+        boulder_density: 3.0,
+    },
+];
+
+impl Biome {
+    pub const ALL: [Biome; BIOME_COUNT] = [
+        Biome::Plains,
+        Biome::Forest,
+        Biome::Desert,
+        Biome::Mountains,
+    ];
+
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    pub fn params(self) -> &'static BiomeParams {
+        &BIOME_PARAMS[self as usize]
+    }
+}
+
+/// Horizontal size of climate zones in blocks.
+const CLIMATE_SCALE: f32 = 512.0;
+/// Distance in climate space over which biomes blend. Larger values give
+/// wider transitions.
+const BLEND_WIDTH: f32 = 0.45;
+
+/// Picks biomes from temperature and humidity noise. Biomes blend into each
+/// other through weights that vary smoothly with the position, so terrain
+/// shaped by the weights has no seams.
+pub struct BiomeMap {
+    seed: u32,
+    temperature: Noise,
+    humidity: Noise,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32) -> Self {
+        BiomeMap {
+            seed,
+            temperature: Noise::new(noise::hash(seed, 0, 0, 3)),
+            humidity: Noise::new(noise::hash(seed, 1, 0, 3)),
+        }
+    }
+
+    /// Temperature and humidity at column (x, z), each in [-1, 1].
+    pub fn climate_at(&self, x: i32, z: i32) -> (f32, f32) {
+        let (x, z) = (x as f32 / CLIMATE_SCALE, z as f32 / CLIMATE_SCALE);
+        let clamp = |v: f32| (v * 2.0).max(-1.0).min(1.0);
+        (
+            clamp(self.temperature.fractal2(x, z, 3)),
+            clamp(self.humidity.fractal2(x, z, 3)),
+        )
+    }
+
+    /// How much each biome contributes at column (x, z), indexed by
+    /// `Biome::index`. The weights add up to 1.
+    pub fn weights_at(&self, x: i32, z: i32) -> [f32; BIOME_COUNT] {
+        let (t, h) = self.climate_at(x, z);
+        let mut weights = [0.0; BIOME_COUNT];
+        let mut total = 0.0;
+        for &biome in Biome::ALL.iter() {
+            let params = biome.params();
+            let dt = t - params.temperature;
+            let dh = h - params.humidity;
+            let w = (-(dt * dt + dh * dh) / (BLEND_WIDTH * BLEND_WIDTH)).exp();
+            weights[biome.index()] = w;
+            total += w;
+        }
+        for w in weights.iter_mut() {
+            *w /= total;
+        }
+        weights
+    }
+
+    /// The biome of column (x, z). Where biomes blend, columns randomly pick
+    /// one of them in proportion to their weights, which dithers the border
+    /// instead of drawing a hard line.
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let weights = self.weights_at(x, z);
+        let mut r = noise::hash_to_unit(noise::hash(self.seed, x, 3, z));
+        for &biome in Biome::ALL.iter() {
+            r -= weights[biome.index()];
+            if r < 0.0 {
+                return biome;
+            }
+        }
+        // Only reachable through rounding errors.
+        Biome::Mountains
+    }
+}
+
+/// Blends a biome parameter by biome weights from `BiomeMap::weights_at`.
+pub fn blend<F>(weights: &[f32; BIOME_COUNT], param: F) -> f32
+where
+    F: Fn(&BiomeParams) -> f32,
+{
+    Biome::ALL
+        .iter()
+        .map(|&biome| weights[biome.index()] * param(biome.params()))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_add_up_to_one() {
+        let map = BiomeMap::new(3);
+        for i in -20..20 {
+            let weights = map.weights_at(i * 97, i * -53);
+            let total: f32 = weights.iter().sum();
+            assert!((total - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn blends_smoothly_between_neighbouring_columns() {
+        let map = BiomeMap::new(3);
+        for z in -64..64 {
+            for x in -64..64 {
+                let a = blend(&map.weights_at(x * 8, z * 8), |p| p.height_amplitude);
+                let b = blend(&map.weights_at(x * 8 + 1, z * 8), |p| p.height_amplitude);
+                assert!((a - b).abs() < 1.0, "{} {}", x, z);
+            }
+        }
+    }
+
+    #[test]
+    fn produces_every_biome() {
+        let map = BiomeMap::new(3);
+        let mut seen = [false; BIOME_COUNT];
+        for z in -32..32 {
+            for x in -32..32 {
+                seen[map.biome_at(x * 64, z * 64).index()] = true;
+            }
+        }
+        assert_eq!(seen, [true; BIOME_COUNT]);
+    }
+}
@@ -1,7 +1,55 @@
-#[repr(u8)]
-#[derive(Clone, Copy, Debug)]
-pub enum Block {
-    Void = 0,
-    Stone = 1,
-    Dirt = 2,
+pub const BLOCK_ID_BITS: u32 = 12;
+pub const BLOCK_ID_MASK: u16 = (1 << BLOCK_ID_BITS) - 1;
+pub const BLOCK_STATE_BITS: u32 = 16 - BLOCK_ID_BITS;
+
+/// A block type from the `BlockRegistry` together with its state. The low
+/// `BLOCK_ID_BITS` bits hold the ID and the remaining bits hold the state,
+/// whose meaning depends on the block type. Block 0 is always void.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Block(pub u16);
+
+impl Block {
+    pub const VOID: Block = Block(0);
+
+    #[inline]
+    pub fn new(id: usize, state: u8) -> Block {
+        debug_assert!(id <= BLOCK_ID_MASK as usize);
+        debug_assert!((state as u32) < (1 << BLOCK_STATE_BITS));
+        Block(id as u16 | (state as u16) << BLOCK_ID_BITS)
+    }
+
+    #[inline]
+    pub fn id(self) -> usize {
+        (self.0 & BLOCK_ID_MASK) as usize
+    }
+
+    #[inline]
+    pub fn state(self) -> u8 {
+        (self.0 >> BLOCK_ID_BITS) as u8
+    }
+
+    #[inline]
+    pub fn with_state(self, state: u8) -> Block {
+        Block::new(self.id(), state)
+    }
+
+    /// The axis a block with `Orientation::Axis` is aligned with. State 0
+    /// is the y axis so blocks without state stand upright.
+    #[inline]
+    pub fn axis(self) -> usize {
+        match self.state() {
+            1 => 0,
+            2 => 2,
+            _ => 1,
+        }
+    }
+
+    #[inline]
+    pub fn with_axis(self, axis: usize) -> Block {
+        self.with_state(match axis {
+            0 => 1,
+            1 => 0,
+            _ => 2,
+        })
+    }
 }
@@ -0,0 +1,806 @@
+use block::Block;
+use block::BLOCK_ID_MASK;
+use cube::Face;
+use cube::FACE_COUNT;
+use fluid::MAX_FLUID_SPREAD;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use tick::BlockTicks;
+
+/// Highest light level. Light decreases by one level per block.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Block names are stored with a one byte length in chunk files.
+pub const MAX_BLOCK_NAME_BYTES: usize = 255;
+
+pub struct BlockType {
+    pub name: String,
+    /// Texture file per face, indexed by `Face::index`.
+    pub textures: [String; FACE_COUNT],
+    /// Texture array layer per face, indexed by `Face::index`.
+    pub layers: [u32; FACE_COUNT],
+    pub solid: bool,
+    pub transparent: bool,
+    /// Transparent blocks are alpha tested unless they are translucent, in
+    /// which case they are blended with what is behind them.
+    pub translucent: bool,
+    pub orientation: Orientation,
+    /// Light level the block emits, up to `MAX_LIGHT`.
+    pub light: u8,
+    pub fluid: Option<FluidProperties>,
+    /// Whether the block falls when there is nothing solid under it.
+    pub falls: bool,
+    /// Height of the top of the block above its bottom, up to 1.
+    pub height: f32,
+    /// Set in code with `BlockRegistry::set_ticks`.
+    pub ticks: BlockTicks,
+}
+
+/// How a fluid flows, see `fluid::FluidSimulation`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FluidProperties {
+    /// How many blocks the fluid flows sideways from a source, up to
+    /// `MAX_FLUID_SPREAD`.
+    pub spread: u8,
+    /// Number of simulation steps between updates of the fluid.
+    pub delay: u32,
+}
+
+impl Default for FluidProperties {
+    fn default() -> Self {
+        FluidProperties {
+            spread: MAX_FLUID_SPREAD,
+            delay: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Orientation {
+    /// The block always looks the same.
+    Fixed,
+    /// The block is aligned with the x, y or z axis, stored in its state.
+    /// The top and bottom textures end up on the faces perpendicular to the
+    /// axis.
+    Axis,
+}
+
+/// How the faces of a block are drawn, in drawing order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum RenderLayer {
+    Opaque,
+    /// Texels are either fully opaque or fully transparent.
+    Cutout,
+    /// Texels are blended with what is behind them.
+    Translucent,
+}
+
+pub const RENDER_LAYER_COUNT: usize = 3;
+
+impl RenderLayer {
+    pub const ALL: [RenderLayer; RENDER_LAYER_COUNT] = [
+        RenderLayer::Opaque,
+        RenderLayer::Cutout,
+        RenderLayer::Translucent,
+    ];
+
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Block types loaded from a definition file. Blocks are assigned IDs in
+/// the order they are defined, starting at 1.
+///
+/// ```text
+/// # Comment.
+/// [log_oak]
+/// textures = log_oak
+/// solid = true
+/// transparent = false
+///
+/// [furnace]
+/// texture = cobblestone_xyz.png
+/// side = furnace_front.png
+/// light = 13
+///
+/// [lava]
+/// textures = lava
+/// solid = false
+/// fluid = true
+/// fluid_spread = 3
+/// ```
+///
+/// The key `textures` picks a texture for every face from the files that
+/// start with the given name, following the suffixes described in
+/// `FACE_SUFFIXES`. The key `texture` sets all faces to a single file,
+/// `side` sets the four faces along x and z and `top` and `bottom` set the
+/// +y and -y faces. Later keys override earlier ones. The key `light`
+/// makes the block emit light. The key `translucent` makes a block
+/// transparent and blends it instead of alpha testing it. The key `fluid`
+/// makes a transparent block flow, `fluid_spread` and `fluid_delay` set its
+/// `FluidProperties`. The key `falls` makes a block fall when there is
+/// nothing solid under it. The key `height` makes a transparent block
+/// lower than a full block, like a slab. Blocks are solid, opaque, dark and have a fixed
+/// orientation unless specified otherwise.
+pub struct BlockRegistry {
+    types: Vec<BlockType>,
+    names: HashMap<String, Block>,
+    texture_names: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn parse_bool(value: &str, line: usize) -> Result<bool, ParseError> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(ParseError {
+            line,
+            message: format!("Expected true or false but got {:?}.", value),
+        }),
+    }
+}
+
+fn parse_in_range<T>(value: &str, what: &str, min: T, max: T, line: usize) -> Result<T, ParseError>
+where
+    T: FromStr + PartialOrd + fmt::Display,
+{
+    match value.parse() {
+        Ok(number) if number >= min && number <= max => Ok(number),
+        _ => Err(ParseError {
+            line,
+            message: format!(
+                "Expected {} from {} to {} but got {:?}.",
+                what, min, max, value
+            ),
+        }),
+    }
+}
+
+const SIDE_FACES: [Face; 4] = [Face::NegX, Face::PosX, Face::NegZ, Face::PosZ];
+
+/// Texture file name suffixes per face, most specific first. A `_py` or `_ny`
+/// texture only covers the +y or -y face, `_y` covers both, `_xz` covers the
+/// four sides and `_xyz` covers every face.
+const FACE_SUFFIXES: [&[&str]; FACE_COUNT] = [
+    &["_xz", "_xyz"],
+    &["_xz", "_xyz"],
+    &["_ny", "_y", "_xyz"],
+    &["_py", "_y", "_xyz"],
+    &["_xz", "_xyz"],
+    &["_xz", "_xyz"],
+];
+
+/// Finds the texture for each face of the block with textures named
+/// `base` according to `FACE_SUFFIXES`.
+fn resolve_textures<F>(base: &str, texture_exists: F) -> Result<[String; FACE_COUNT], Face>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut textures: [String; FACE_COUNT] = Default::default();
+    for &face in Face::ALL.iter() {
+        textures[face.index()] = FACE_SUFFIXES[face.index()]
+            .iter()
+            .map(|suffix| format!("{}{}.png", base, suffix))
+            .find(|name| texture_exists(name))
+            .ok_or(face)?;
+    }
+    Ok(textures)
+}
+
+impl BlockRegistry {
+    fn void() -> BlockType {
+        BlockType {
+            name: String::from("void"),
+            textures: Default::default(),
+            layers: [0; FACE_COUNT],
+            solid: false,
+            transparent: true,
+            translucent: false,
+            orientation: Orientation::Fixed,
+            light: 0,
+            fluid: None,
+            falls: false,
+            height: 1.0,
+            ticks: BlockTicks::default(),
+        }
+    }
+
+    /// Parses block definitions. The function `texture_exists` tells
+    /// whether a texture file is available and is used to resolve the
+    /// `textures` key.
+    pub fn parse<F>(source: &str, texture_exists: F) -> Result<Self, ParseError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut types = vec![Self::void()];
+        let mut names = HashMap::new();
+        names.insert(String::from("void"), Block::VOID);
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let line = match line.find('#') {
+                Some(i) => &line[..i],
+                None => line,
+            }
+            .trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: String::from("Expected ] at the end of the block name."),
+                    });
+                }
+                let name = line[1..line.len() - 1].trim();
+                if name.len() > MAX_BLOCK_NAME_BYTES {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: format!(
+                            "Block names can be at most {} bytes long.",
+                            MAX_BLOCK_NAME_BYTES
+                        ),
+                    });
+                }
+                if names.contains_key(name) {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: format!("Block {:?} is defined twice.", name),
+                    });
+                }
+                if types.len() > BLOCK_ID_MASK as usize {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: String::from("Too many block types."),
+                    });
+                }
+                names.insert(String::from(name), Block::new(types.len(), 0));
+                types.push(BlockType {
+                    name: String::from(name),
+                    textures: Default::default(),
+                    layers: [0; FACE_COUNT],
+                    solid: true,
+                    transparent: false,
+                    translucent: false,
+                    orientation: Orientation::Fixed,
+                    light: 0,
+                    fluid: None,
+                    falls: false,
+                    height: 1.0,
+                    ticks: BlockTicks::default(),
+                });
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: format!("Expected key = value but got {:?}.", line),
+                    });
+                }
+            };
+
+            if types.len() == 1 {
+                return Err(ParseError {
+                    line: line_number,
+                    message: String::from("Expected a [block] before the first property."),
+                });
+            }
+
+            let block_type = types.last_mut().unwrap();
+            let mut set_textures = |faces: &[Face]| {
+                for &face in faces {
+                    block_type.textures[face.index()] = String::from(value);
+                }
+            };
+
+            match key {
+                "textures" => {
+                    let textures = resolve_textures(value, &texture_exists);
+                    block_type.textures = textures.map_err(|face| ParseError {
+                        line: line_number,
+                        message: format!(
+                            "No texture {:?} with a suffix in {:?} for face {:?}.",
+                            value,
+                            FACE_SUFFIXES[face.index()],
+                            face
+                        ),
+                    })?;
+                }
+                "texture" => set_textures(&Face::ALL),
+                "side" => set_textures(&SIDE_FACES),
+                "top" => set_textures(&[Face::PosY]),
+                "bottom" => set_textures(&[Face::NegY]),
+                "solid" => block_type.solid = parse_bool(value, line_number)?,
+                "transparent" => block_type.transparent = parse_bool(value, line_number)?,
+                "translucent" => {
+                    block_type.translucent = parse_bool(value, line_number)?;
+                    block_type.transparent |= block_type.translucent;
+                }
+                "orientation" => {
+                    block_type.orientation = match value {
+                        "fixed" => Orientation::Fixed,
+                        "axis" => Orientation::Axis,
+                        _ => {
+                            return Err(ParseError {
+                                line: line_number,
+                                message: format!("Expected fixed or axis but got {:?}.", value),
+                            });
+                        }
+                    }
+                }
+                "light" => {
+                    block_type.light =
+                        parse_in_range(value, "a light level", 0, MAX_LIGHT, line_number)?;
+                }
+                "falls" => block_type.falls = parse_bool(value, line_number)?,
+                "height" => {
+                    block_type.height =
+                        parse_in_range(value, "a height", 0.0, 1.0, line_number)?;
+                    block_type.transparent |= block_type.height < 1.0;
+                }
+                "fluid" => {
+                    block_type.fluid = if parse_bool(value, line_number)? {
+                        block_type.transparent = true;
+                        Some(block_type.fluid.unwrap_or_default())
+                    } else {
+                        None
+                    };
+                }
+                "fluid_spread" => {
+                    let spread =
+                        parse_in_range(value, "a spread", 1, MAX_FLUID_SPREAD, line_number)?;
+                    block_type.transparent = true;
+                    block_type.fluid.get_or_insert_with(Default::default).spread = spread;
+                }
+                "fluid_delay" => {
+                    let delay = parse_in_range(value, "a delay", 1, u32::max_value(), line_number)?;
+                    block_type.transparent = true;
+                    block_type.fluid.get_or_insert_with(Default::default).delay = delay;
+                }
+                _ => {
+                    return Err(ParseError {
+                        line: line_number,
+                        message: format!("Unknown property {:?}.", key),
+                    });
+                }
+            }
+        }
+
+        // Assign texture array layers, sharing layers between faces that
+        // use the same texture.
+        let mut texture_names: Vec<String> = Vec::new();
+        for block_type in types.iter_mut().skip(1) {
+            for &face in Face::ALL.iter() {
+                let texture = &block_type.textures[face.index()];
+                if texture.is_empty() {
+                    // Blocks without texture are not rendered anyway.
+                    continue;
+                }
+                let layer = match texture_names.iter().position(|name| name == texture) {
+                    Some(layer) => layer,
+                    None => {
+                        texture_names.push(texture.clone());
+                        texture_names.len() - 1
+                    }
+                };
+                block_type.layers[face.index()] = layer as u32;
+            }
+        }
+
+        Ok(BlockRegistry {
+            types,
+            names,
+            texture_names,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let source = ::assets::file_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&source, |name| directory.join(name).is_file())
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))
+    }
+
+    #[inline]
+    pub fn get(&self, block: Block) -> &BlockType {
+        &self.types[block.id()]
+    }
+
+    /// Looks up a block by the name it was defined with.
+    pub fn block(&self, name: &str) -> Option<Block> {
+        self.names.get(name).cloned()
+    }
+
+    /// Number of block types including void.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Whether every block type of `previous` has the same ID here, so
+    /// blocks created with `previous` mean the same with this registry.
+    /// This holds when block types were only added after the existing ones.
+    pub fn keeps_ids_of(&self, previous: &BlockRegistry) -> bool {
+        previous.types.len() <= self.types.len()
+            && previous
+                .types
+                .iter()
+                .zip(self.types.iter())
+                .all(|(a, b)| a.name == b.name)
+    }
+
+    /// Iterates over all block types except void.
+    pub fn blocks(&self) -> impl Iterator<Item = Block> {
+        (1..self.types.len()).map(|id| Block::new(id, 0))
+    }
+
+    /// Whether the block completely hides the faces of its neighbours.
+    #[inline]
+    pub fn is_opaque(&self, block: Block) -> bool {
+        !self.types[block.id()].transparent
+    }
+
+    #[inline]
+    pub fn render_layer(&self, block: Block) -> RenderLayer {
+        let block_type = &self.types[block.id()];
+        if !block_type.transparent {
+            RenderLayer::Opaque
+        } else if block_type.translucent {
+            RenderLayer::Translucent
+        } else {
+            RenderLayer::Cutout
+        }
+    }
+
+    /// Whether the block stops movement.
+    #[inline]
+    pub fn is_solid(&self, block: Block) -> bool {
+        self.types[block.id()].solid
+    }
+
+    /// Height of the top of the block above its bottom.
+    #[inline]
+    pub fn height(&self, block: Block) -> f32 {
+        self.types[block.id()].height
+    }
+
+    /// How high the block stops movement, 0 for blocks that are not solid.
+    #[inline]
+    pub fn solid_height(&self, block: Block) -> f32 {
+        let block_type = &self.types[block.id()];
+        if block_type.solid {
+            block_type.height
+        } else {
+            0.0
+        }
+    }
+
+    #[inline]
+    pub fn fluid(&self, block: Block) -> Option<FluidProperties> {
+        self.types[block.id()].fluid
+    }
+
+    #[inline]
+    pub fn falls(&self, block: Block) -> bool {
+        self.types[block.id()].falls
+    }
+
+    #[inline]
+    pub fn ticks(&self, block: Block) -> BlockTicks {
+        self.types[block.id()].ticks
+    }
+
+    /// Gives all blocks of the type of `block` behaviour over time.
+    pub fn set_ticks(&mut self, block: Block, ticks: BlockTicks) {
+        self.types[block.id()].ticks = ticks;
+    }
+
+    /// Light level the block emits.
+    #[inline]
+    pub fn light(&self, block: Block) -> u8 {
+        self.types[block.id()].light
+    }
+
+    /// The block to place when `block` is placed against `face` of another
+    /// block. Blocks with `Orientation::Axis` align with the face normal.
+    pub fn placed(&self, block: Block, face: Face) -> Block {
+        match self.types[block.id()].orientation {
+            Orientation::Fixed => block,
+            Orientation::Axis => block.with_axis(face.axis()),
+        }
+    }
+
+    /// The texture array layer shown on `face` of `block` and whether its
+    /// texture coordinates are swapped. Coordinates are swapped on the
+    /// sides of blocks lying along the x or z axis so the side texture
+    /// runs along the axis.
+    pub fn face_texture(&self, block: Block, face: Face) -> (u32, bool) {
+        let block_type = &self.types[block.id()];
+        match block_type.orientation {
+            Orientation::Fixed => (block_type.layers[face.index()], false),
+            Orientation::Axis => {
+                let axis = block.axis();
+                // Rotate the face into the frame of the upright block.
+                let texture_face = if face.axis() == axis {
+                    Face::from_axis(1, face.is_positive())
+                } else if face.axis() == 1 {
+                    Face::from_axis(axis, face.is_positive())
+                } else {
+                    face
+                };
+                let swap = face.axis() != axis && face.tex_axes().1 != axis;
+                (block_type.layers[texture_face.index()], swap)
+            }
+        }
+    }
+
+    /// Texture files in texture array layer order.
+    pub fn texture_names(&self) -> &[String] {
+        &self.texture_names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+        # Comment.
+        [stone]
+        texture = stone_xyz.png
+
+        [log_oak] # Trailing comment.
+        textures = log_oak
+
+        [glass]
+        texture = glass.png
+        transparent = true
+    ";
+
+    const TEXTURES: [&str; 7] = [
+        "stone_xyz.png",
+        "log_oak_xz.png",
+        "log_oak_y.png",
+        "melon_xz.png",
+        "melon_py.png",
+        "melon_ny.png",
+        "melon_y.png",
+    ];
+
+    fn texture_exists(name: &str) -> bool {
+        TEXTURES.contains(&name)
+    }
+
+    #[test]
+    fn assigns_ids_in_order() {
+        let registry = BlockRegistry::parse(SOURCE, texture_exists).unwrap();
+        assert_eq!(registry.len(), 4);
+        assert_eq!(registry.block("void"), Some(Block::VOID));
+        assert_eq!(registry.block("stone"), Some(Block(1)));
+        assert_eq!(registry.block("log_oak"), Some(Block(2)));
+        assert_eq!(registry.block("glass"), Some(Block(3)));
+        assert_eq!(registry.block("dirt"), None);
+        assert!(registry.is_opaque(Block(1)));
+        assert!(!registry.is_opaque(Block(3)));
+        assert!(!registry.is_opaque(Block::VOID));
+        assert!(registry.is_solid(Block(3)));
+        assert!(!registry.is_solid(Block::VOID));
+    }
+
+    #[test]
+    fn keeps_ids_when_block_types_are_added_at_the_end() {
+        let registry = BlockRegistry::parse(SOURCE, texture_exists).unwrap();
+        let parse = |source: &str| BlockRegistry::parse(source, |_| true).unwrap();
+        assert!(registry.keeps_ids_of(&registry));
+        assert!(parse(&format!("{}\n[dirt]", SOURCE)).keeps_ids_of(&registry));
+        assert!(!parse("[stone]\n[glass]").keeps_ids_of(&registry));
+        assert!(!parse("[stone]\n[glass]\n[log_oak]").keeps_ids_of(&registry));
+        assert!(!registry.keeps_ids_of(&parse(&format!("{}\n[dirt]", SOURCE))));
+    }
+
+    #[test]
+    fn picks_render_layers() {
+        let source = format!(
+            "{}\n[water]\ntexture = water.png\ntranslucent = true",
+            SOURCE
+        );
+        let registry = BlockRegistry::parse(&source, |_| true).unwrap();
+        assert_eq!(registry.render_layer(Block(1)), RenderLayer::Opaque);
+        assert_eq!(registry.render_layer(Block(3)), RenderLayer::Cutout);
+        assert_eq!(registry.render_layer(Block(4)), RenderLayer::Translucent);
+        assert!(!registry.is_opaque(Block(4)));
+    }
+
+    #[test]
+    fn shares_texture_layers() {
+        let registry = BlockRegistry::parse(SOURCE, texture_exists).unwrap();
+        assert_eq!(
+            registry.texture_names(),
+            &[
+                "stone_xyz.png",
+                "log_oak_xz.png",
+                "log_oak_y.png",
+                "glass.png"
+            ]
+        );
+        assert_eq!(registry.get(Block(1)).layers, [0; FACE_COUNT]);
+        let log = registry.get(Block(2));
+        assert_eq!(log.layers[Face::PosX.index()], 1);
+        assert_eq!(log.layers[Face::PosY.index()], 2);
+        assert_eq!(log.layers[Face::NegY.index()], 2);
+        assert_eq!(log.layers[Face::NegZ.index()], 1);
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let err = BlockRegistry::parse("[stone]\nsolid = maybe", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        let err = BlockRegistry::parse("texture = stone_xyz.png", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 1);
+        let err = BlockRegistry::parse("[stone]\n[stone]", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+        let long_name = "x".repeat(MAX_BLOCK_NAME_BYTES);
+        assert!(BlockRegistry::parse(&format!("[{}]", long_name), |_| true).is_ok());
+        let err = BlockRegistry::parse(&format!("[stone]\n[{}x]", long_name), |_| true)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn parses_light_levels() {
+        let registry = BlockRegistry::parse(
+            "[stone]\ntexture = stone_xyz.png\n[lamp]\ntexture = stone_xyz.png\nlight = 14",
+            texture_exists,
+        )
+        .unwrap();
+        assert_eq!(registry.light(Block(1)), 0);
+        assert_eq!(registry.light(Block(2)), 14);
+        let err = BlockRegistry::parse("[lamp]\nlight = 16", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn parses_fluids() {
+        let registry = BlockRegistry::parse(
+            "[stone]\n[water]\nfluid = true\n[lava]\nfluid_delay = 90\nfluid_spread = 3",
+            texture_exists,
+        )
+        .unwrap();
+        assert_eq!(registry.fluid(Block(1)), None);
+        assert_eq!(registry.fluid(Block(2)), Some(FluidProperties::default()));
+        assert_eq!(
+            registry.fluid(Block(3)),
+            Some(FluidProperties {
+                spread: 3,
+                delay: 90,
+            })
+        );
+        assert!(!registry.is_opaque(Block(3)));
+        let err = BlockRegistry::parse("[lava]\nfluid_spread = 8", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn parses_heights() {
+        let registry = BlockRegistry::parse(
+            "[stone]\n[slab]\nheight = 0.5\n[water]\nsolid = false\nheight = 0.5",
+            texture_exists,
+        )
+        .unwrap();
+        assert_eq!(registry.solid_height(Block(1)), 1.0);
+        assert_eq!(registry.solid_height(Block(2)), 0.5);
+        assert_eq!(registry.solid_height(Block(3)), 0.0);
+        assert_eq!(registry.height(Block(3)), 0.5);
+        assert!(!registry.is_opaque(Block(2)));
+        let err = BlockRegistry::parse("[slab]\nheight = 1.5", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn resolves_textures_by_suffix() {
+        let registry = BlockRegistry::parse(
+            "[stone]\ntextures = stone\n[melon]\ntextures = melon",
+            texture_exists,
+        )
+        .unwrap();
+        let stone = &registry.get(Block(1)).textures;
+        for &face in Face::ALL.iter() {
+            assert_eq!(stone[face.index()], "stone_xyz.png");
+        }
+        // The more specific _py and _ny textures win over _y.
+        let melon = &registry.get(Block(2)).textures;
+        assert_eq!(melon[Face::PosY.index()], "melon_py.png");
+        assert_eq!(melon[Face::NegY.index()], "melon_ny.png");
+        assert_eq!(melon[Face::PosX.index()], "melon_xz.png");
+        assert_eq!(melon[Face::NegZ.index()], "melon_xz.png");
+    }
+
+    #[test]
+    fn explicit_textures_override_suffixes() {
+        let registry = BlockRegistry::parse(
+            "[log]\ntextures = log_oak\ntop = melon_py.png",
+            texture_exists,
+        )
+        .unwrap();
+        let log = &registry.get(Block(1)).textures;
+        assert_eq!(log[Face::PosY.index()], "melon_py.png");
+        assert_eq!(log[Face::NegY.index()], "log_oak_y.png");
+        assert_eq!(log[Face::PosX.index()], "log_oak_xz.png");
+    }
+
+    #[test]
+    fn reports_missing_textures() {
+        let err = BlockRegistry::parse("[dirt]\n\ntextures = dirt", texture_exists)
+            .err()
+            .unwrap();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn oriented_blocks_follow_their_axis() {
+        let registry = BlockRegistry::parse(
+            "[log_oak]\ntextures = log_oak\norientation = axis\n[stone]\ntextures = stone",
+            texture_exists,
+        )
+        .unwrap();
+        let log = registry.block("log_oak").unwrap();
+        let stone = registry.block("stone").unwrap();
+        let (side, end) = (0, 1);
+
+        assert_eq!(registry.placed(stone, Face::PosX), stone);
+        assert_eq!(registry.placed(log, Face::PosY), log);
+        assert_eq!(registry.placed(log, Face::NegX).axis(), 0);
+        assert_eq!(registry.placed(log, Face::PosZ).axis(), 2);
+
+        let upright = registry.placed(log, Face::PosY);
+        assert_eq!(registry.face_texture(upright, Face::PosY), (end, false));
+        assert_eq!(registry.face_texture(upright, Face::PosX), (side, false));
+        assert_eq!(registry.face_texture(upright, Face::NegZ), (side, false));
+
+        let along_x = registry.placed(log, Face::PosX);
+        assert_eq!(registry.face_texture(along_x, Face::NegX), (end, false));
+        assert_eq!(registry.face_texture(along_x, Face::PosY), (side, true));
+        assert_eq!(registry.face_texture(along_x, Face::PosZ), (side, true));
+
+        let along_z = registry.placed(log, Face::PosZ);
+        assert_eq!(registry.face_texture(along_z, Face::PosZ), (end, false));
+        assert_eq!(registry.face_texture(along_z, Face::PosY), (side, false));
+        assert_eq!(registry.face_texture(along_z, Face::PosX), (side, true));
+    }
+}
@@ -64,6 +64,11 @@ impl Camera {
             * Quaternion::from_axis_angle(Vector3::unit_x(), -self.pitch)
     }
 
+    /// The direction the camera is looking in, in world space.
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation() * -Vector3::unit_z()
+    }
+
     pub fn pos_from_wld_to_cam_space(&self) -> Matrix4<f32> {
         // Directly construct the inverse cam_to_wld transformation matrix.
         Matrix4::from(self.orientation().invert()) * Matrix4::from_translation(-self.position)
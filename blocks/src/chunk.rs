@@ -1,26 +1,62 @@
+use biome::Biome;
 use block::Block;
 use cgmath::*;
+use light::Light;
+use palette::BlockMut;
+use palette::PalettedBlocks;
 
-pub const CHUNK_SIDE_BLOCKS: usize = 16;
+pub const CHUNK_SIDE_BITS: usize = 4;
+pub const CHUNK_SIDE_BLOCKS: usize = 1 << CHUNK_SIDE_BITS;
+pub const CHUNK_SIDE_MASK: usize = CHUNK_SIDE_BLOCKS - 1;
 pub const CHUNK_TOTAL_BLOCKS: usize = CHUNK_SIDE_BLOCKS * CHUNK_SIDE_BLOCKS * CHUNK_SIDE_BLOCKS;
 
 pub struct Chunk {
-    pub blocks: [Block; CHUNK_TOTAL_BLOCKS],
-    pub position_indices: Vector3<u32>,
+    pub blocks: PalettedBlocks,
+    /// Biome per column, indexed by `z * CHUNK_SIDE_BLOCKS + x`.
+    pub biomes: [Biome; CHUNK_SIDE_BLOCKS * CHUNK_SIDE_BLOCKS],
+    /// Light per block, indexed like `blocks`. It is not saved and is
+    /// computed again when the chunk is inserted into a world.
+    pub light: Vec<Light>,
+    pub position_indices: Vector3<i32>,
 }
 
 impl Chunk {
+    pub fn new(position_indices: Vector3<i32>) -> Self {
+        Chunk {
+            blocks: PalettedBlocks::new(Block::VOID),
+            biomes: [Biome::Plains; CHUNK_SIDE_BLOCKS * CHUNK_SIDE_BLOCKS],
+            light: vec![Light::DARK; CHUNK_TOTAL_BLOCKS],
+            position_indices,
+        }
+    }
+
     #[inline]
     pub fn block_index(x: usize, y: usize, z: usize) -> usize {
         (z * CHUNK_SIDE_BLOCKS + y) * CHUNK_SIDE_BLOCKS + x
     }
 
     pub fn block_at(&self, x: usize, y: usize, z: usize) -> &Block {
-        &self.blocks[Self::block_index(x, y, z)]
+        self.blocks.get(Self::block_index(x, y, z))
+    }
+
+    pub fn block_at_mut(&mut self, x: usize, y: usize, z: usize) -> BlockMut {
+        self.blocks.get_mut(Self::block_index(x, y, z))
+    }
+
+    pub fn light_at(&self, x: usize, y: usize, z: usize) -> Light {
+        self.light[Self::block_index(x, y, z)]
+    }
+
+    pub fn light_at_mut(&mut self, x: usize, y: usize, z: usize) -> &mut Light {
+        &mut self.light[Self::block_index(x, y, z)]
+    }
+
+    pub fn biome_at(&self, x: usize, z: usize) -> Biome {
+        self.biomes[z * CHUNK_SIDE_BLOCKS + x]
     }
 
-    pub fn block_at_mut(&mut self, x: usize, y: usize, z: usize) -> &mut Block {
-        &mut self.blocks[Self::block_index(x, y, z)]
+    pub fn biome_at_mut(&mut self, x: usize, z: usize) -> &mut Biome {
+        &mut self.biomes[z * CHUNK_SIDE_BLOCKS + x]
     }
 
     pub fn blocks(&self) -> BlockIter {
@@ -57,7 +93,7 @@ impl<'a> Iterator for BlockIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < CHUNK_TOTAL_BLOCKS {
-            let block = self.chunk.blocks[self.index];
+            let block = *self.chunk.blocks.get(self.index);
             let position = self.offset + self.position.cast::<f32>().unwrap();
             self.index += 1;
             self.position.x += 1;
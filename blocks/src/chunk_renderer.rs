@@ -1,24 +1,34 @@
 use assets::file_to_bytes;
 use assets::Assets;
-use block::Block;
+use block_registry::BlockRegistry;
+use block_registry::RenderLayer;
+use block_registry::RENDER_LAYER_COUNT;
+use cgmath::Array;
+use cgmath::InnerSpace;
 use cgmath::Matrix4;
+use cgmath::Vector2;
 use cgmath::Vector3;
 use cgmath_ext::*;
 use chunk;
-use chunk::Chunk;
-use cube;
+use frustrum::FrustrumPlanes;
 use gl;
 use glw;
 use glw::prelude::*;
 use image;
+use mesher;
+use mesher::ChunkMesh;
+use mesher::MeshVertex;
 use program::*;
 use shader::*;
+use std::collections::HashMap;
+
+/// Width and height of every block texture.
+const TEXTURE_SIZE: u32 = 32;
 
 pub struct ChunkRendererChanges {
     pub vert: bool,
     pub frag: bool,
-    pub dirt: bool,
-    pub stone: bool,
+    pub textures: bool,
 }
 
 impl ChunkRendererChanges {
@@ -26,8 +36,7 @@ impl ChunkRendererChanges {
         ChunkRendererChanges {
             vert: false,
             frag: false,
-            dirt: false,
-            stone: false,
+            textures: false,
         }
     }
 
@@ -35,28 +44,57 @@ impl ChunkRendererChanges {
         ChunkRendererChanges {
             vert: true,
             frag: true,
-            dirt: true,
-            stone: true,
+            textures: true,
         }
     }
 }
 
+/// What happened to the chunks with a mesh during the last render.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkRenderStats {
+    pub drawn: usize,
+    /// Chunks outside the view frustrum.
+    pub culled: usize,
+    /// Chunks in the view frustrum that are hidden behind other chunks.
+    pub occluded: usize,
+}
+
 pub struct ChunkRenderer {
     vertex_shader: VertexShader,
     fragment_shader: FragmentShader,
     program: Program,
     pos_from_wld_to_clp_space_loc: Option<glw::UniformLocation<[f32; 16]>>,
+    pos_from_chk_to_wld_space_loc: Option<glw::UniformLocation<[f32; 16]>>,
+    render_layer_loc: Option<glw::UniformLocation<i32>>,
+    vs_ver_pos_loc: Option<glw::AttributeLocation>,
+    vs_tex_pos_loc: Option<glw::AttributeLocation>,
+    vs_tex_layer_loc: Option<glw::AttributeLocation>,
+    vs_light_loc: Option<glw::AttributeLocation>,
+    vs_ao_loc: Option<glw::AttributeLocation>,
     texture_atlas_name: glw::TextureName,
+    chunk_buffers: HashMap<Vector3<i32>, ChunkBuffers>,
+    /// Blocks that are not part of a chunk, in world space.
+    entity_buffers: LayerBuffers,
+}
+
+struct LayerBuffers {
     vertex_array_name: glw::VertexArrayName,
-    #[allow(unused)]
     vertex_buffer_name: glw::BufferName,
-    #[allow(unused)]
-    element_buffer_name: glw::BufferName,
-    block_buffer_name: glw::BufferName,
+    vertex_count: usize,
+}
+
+struct ChunkBuffers {
+    /// Indexed by `RenderLayer::index`.
+    layers: Vec<LayerBuffers>,
+    /// A copy of the translucent vertices that is sorted again when the
+    /// camera moves.
+    translucent_vertices: Vec<MeshVertex>,
+    /// The camera block the translucent vertices were sorted for.
+    sorted_for: Option<Vector3<i32>>,
 }
 
 impl ChunkRenderer {
-    pub unsafe fn new(assets: &Assets) -> Self {
+    pub unsafe fn new(assets: &Assets, block_registry: &BlockRegistry) -> Self {
         let vertex_shader_name = glw::create_shader(glw::VERTEX_SHADER).unwrap();
         let fragment_shader_name = glw::create_shader(glw::FRAGMENT_SHADER).unwrap();
         let program_name = glw::create_program().unwrap();
@@ -64,81 +102,21 @@ impl ChunkRenderer {
         glw::attach_shader(&program_name, vertex_shader_name.as_ref());
         glw::attach_shader(&program_name, fragment_shader_name.as_ref());
 
-        let [vertex_buffer_name, element_buffer_name, block_buffer_name] =
-            glw::gen_buffers_move::<[_; 3]>().unwrap_all().unwrap();
-
         let [texture_atlas_name] = glw::gen_textures_move::<[_; 1]>().unwrap_all().unwrap();
 
-        let [vertex_array_name] = glw::gen_vertex_arrays_move::<[_; 1]>()
-            .unwrap_all()
-            .unwrap();
-
-        glw::bind_vertex_array(&vertex_array_name);
-
-        // Set up vertex buffer
-        {
-            glw::bind_buffer(glw::ARRAY_BUFFER, &vertex_buffer_name);
-
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                ::std::mem::size_of_val(&cube::VERTEX_DATA) as isize,
-                cube::VERTEX_DATA.as_ptr() as *const ::std::os::raw::c_void,
-                gl::STATIC_DRAW,
-            );
-        }
-
-        // Set up element buffer
-        {
-            glw::bind_buffer(glw::ELEMENT_ARRAY_BUFFER, &element_buffer_name);
-
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                ::std::mem::size_of_val(&cube::ELEMENT_DATA) as isize,
-                cube::ELEMENT_DATA.as_ptr() as *const ::std::os::raw::c_void,
-                gl::STATIC_DRAW,
-            );
-        }
-
-        // Set up block buffer
-        {
-            glw::bind_buffer(glw::ARRAY_BUFFER, &block_buffer_name);
-
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                ::std::mem::size_of::<[Block; chunk::CHUNK_TOTAL_BLOCKS]>() as isize,
-                ::std::ptr::null(),
-                gl::STREAM_DRAW,
-            );
-        }
-
         {
             glw::bind_texture(glw::TEXTURE_2D_ARRAY, &texture_atlas_name);
 
-            gl::TexStorage3D(
-                gl::TEXTURE_2D_ARRAY, // target
-                6,                    // levels
-                gl::RGBA8,            // internal format
-                32,                   // width
-                32,                   // height
-                2,                    // depth (layer count)
-            );
-
             glw::tex_parameter_i(
                 glw::TEXTURE_2D_ARRAY,
                 glw::TEXTURE_MIN_FILTER,
                 glw::LINEAR_MIPMAP_LINEAR,
             );
             glw::tex_parameter_i(glw::TEXTURE_2D_ARRAY, glw::TEXTURE_MAG_FILTER, glw::NEAREST);
-            glw::tex_parameter_i(
-                glw::TEXTURE_2D_ARRAY,
-                glw::TEXTURE_WRAP_S,
-                glw::CLAMP_TO_EDGE,
-            );
-            glw::tex_parameter_i(
-                glw::TEXTURE_2D_ARRAY,
-                glw::TEXTURE_WRAP_T,
-                glw::CLAMP_TO_EDGE,
-            );
+            // Merged quads from the greedy mesher use texture coordinates
+            // beyond 1.0 and rely on the texture repeating.
+            glw::tex_parameter_i(glw::TEXTURE_2D_ARRAY, glw::TEXTURE_WRAP_S, glw::REPEAT);
+            glw::tex_parameter_i(glw::TEXTURE_2D_ARRAY, glw::TEXTURE_WRAP_T, glw::REPEAT);
         }
 
         let mut renderer = ChunkRenderer {
@@ -146,19 +124,29 @@ impl ChunkRenderer {
             fragment_shader: FragmentShader::Uncompiled(fragment_shader_name),
             program: Program::Unlinked(program_name),
             pos_from_wld_to_clp_space_loc: None,
+            pos_from_chk_to_wld_space_loc: None,
+            render_layer_loc: None,
+            vs_ver_pos_loc: None,
+            vs_tex_pos_loc: None,
+            vs_tex_layer_loc: None,
+            vs_light_loc: None,
+            vs_ao_loc: None,
             texture_atlas_name,
-            vertex_array_name,
-            vertex_buffer_name,
-            element_buffer_name,
-            block_buffer_name,
+            chunk_buffers: HashMap::new(),
+            entity_buffers: LayerBuffers::new(),
         };
 
-        renderer.update(assets, ChunkRendererChanges::all());
+        renderer.update(assets, block_registry, ChunkRendererChanges::all());
 
         renderer
     }
 
-    pub unsafe fn update(&mut self, assets: &Assets, changes: ChunkRendererChanges) {
+    pub unsafe fn update(
+        &mut self,
+        assets: &Assets,
+        block_registry: &BlockRegistry,
+        changes: ChunkRendererChanges,
+    ) {
         if changes.vert {
             let file_path = &assets.chunk_renderer_vert;
             match file_to_bytes(&file_path) {
@@ -213,6 +201,14 @@ impl ChunkRenderer {
                                 &program_name,
                                 static_cstr!("pos_from_wld_to_clp_space"),
                             );
+                            self.pos_from_chk_to_wld_space_loc = glw::get_uniform_location(
+                                &program_name,
+                                static_cstr!("pos_from_chk_to_wld_space"),
+                            );
+                            self.render_layer_loc = glw::get_uniform_location(
+                                &program_name,
+                                static_cstr!("render_layer"),
+                            );
 
                             // Bind the program.
                             glw::use_program(&program_name);
@@ -230,205 +226,296 @@ impl ChunkRenderer {
                                 }
                             }
 
-                            // Set up the vertex array object.
-                            {
-                                glw::bind_vertex_array(&self.vertex_array_name);
-
-                                // Set vertex position and texture position attributes.
-                                {
-                                    glw::bind_buffer(glw::ARRAY_BUFFER, &self.vertex_buffer_name);
-
-                                    // Bind vertex position attribute.
-                                    match glw::get_attrib_location(
-                                        &program_name,
-                                        static_cstr!("vs_ver_pos"),
-                                    ) {
-                                        Some(loc) => {
-                                            gl::EnableVertexAttribArray(loc.as_u32());
-                                            gl::VertexAttribPointer(
-                                                loc.as_u32(),                                 // index
-                                                3,         // size (component count)
-                                                gl::FLOAT, // type (component type)
-                                                gl::FALSE, // normalized
-                                                ::std::mem::size_of::<cube::Vertex>() as i32, // stride
-                                                0 as *const ::std::os::raw::c_void, // offset
-                                            );
-                                        }
-                                        None => {
-                                            eprintln!("Could not find vs_ver_pos attribute.");
-                                        }
-                                    }
-
-                                    // Bind texture coordinate attribute.
-                                    match glw::get_attrib_location(
-                                        &program_name,
-                                        static_cstr!("vs_tex_pos"),
-                                    ) {
-                                        Some(loc) => {
-                                            gl::EnableVertexAttribArray(loc.as_u32());
-                                            gl::VertexAttribPointer(
-                                                loc.as_u32(),                                 // index
-                                                2,         // size (component count)
-                                                gl::FLOAT, // type (component type)
-                                                gl::FALSE, // normalized
-                                                ::std::mem::size_of::<cube::Vertex>() as i32, // stride
-                                                ::std::mem::size_of::<Vector3<f32>>()
-                                                    as *const ::std::os::raw::c_void, // offset
-                                            );
-                                        }
-                                        None => {
-                                            eprintln!("Could not find vs_tex_pos attribute.");
-                                        }
-                                    }
-                                }
+                            // Update attribute locations.
+                            self.vs_ver_pos_loc =
+                                glw::get_attrib_location(&program_name, static_cstr!("vs_ver_pos"));
+                            if self.vs_ver_pos_loc.is_none() {
+                                eprintln!("Could not find vs_ver_pos attribute.");
+                            }
 
-                                // Set block type attribute.
-                                {
-                                    glw::bind_buffer(glw::ARRAY_BUFFER, &self.block_buffer_name);
-
-                                    // Bind block type attribute.
-                                    match glw::get_attrib_location(
-                                        &program_name,
-                                        static_cstr!("vs_blk_type"),
-                                    ) {
-                                        Some(loc) => {
-                                            gl::EnableVertexAttribArray(loc.as_u32());
-                                            gl::VertexAttribIPointer(
-                                                loc.as_u32(),                          // index
-                                                ::std::mem::size_of::<Block>() as i32, // size (component count)
-                                                gl::UNSIGNED_BYTE, // type (component type)
-                                                ::std::mem::size_of::<Block>() as i32, // stride
-                                                0 as *const ::std::os::raw::c_void, // offset
-                                            );
-                                            gl::VertexAttribDivisor(
-                                                loc.as_u32(), // index
-                                                1,            // advance every # instances
-                                            );
-                                        }
-                                        None => {
-                                            eprintln!("Could not find vs_blk_type attribute.");
-                                        }
-                                    }
-                                }
+                            self.vs_tex_pos_loc =
+                                glw::get_attrib_location(&program_name, static_cstr!("vs_tex_pos"));
+                            if self.vs_tex_pos_loc.is_none() {
+                                eprintln!("Could not find vs_tex_pos attribute.");
+                            }
+
+                            self.vs_tex_layer_loc = glw::get_attrib_location(
+                                &program_name,
+                                static_cstr!("vs_tex_layer"),
+                            );
+                            if self.vs_tex_layer_loc.is_none() {
+                                eprintln!("Could not find vs_tex_layer attribute.");
+                            }
 
-                                // Bind the element array buffer.
-                                glw::bind_buffer(
-                                    glw::ELEMENT_ARRAY_BUFFER,
-                                    &self.element_buffer_name,
-                                );
+                            self.vs_light_loc =
+                                glw::get_attrib_location(&program_name, static_cstr!("vs_light"));
+                            if self.vs_light_loc.is_none() {
+                                eprintln!("Could not find vs_light attribute.");
                             }
+
+                            self.vs_ao_loc =
+                                glw::get_attrib_location(&program_name, static_cstr!("vs_ao"));
+                            if self.vs_ao_loc.is_none() {
+                                eprintln!("Could not find vs_ao attribute.");
+                            }
+                        }
+                    }
+
+                    // Attribute locations may have changed, so the
+                    // vertex array objects need to be set up again.
+                    for buffers in self.chunk_buffers.values() {
+                        for layer in buffers.layers.iter() {
+                            self.set_up_vertex_array(layer);
                         }
                     }
+                    self.set_up_vertex_array(&self.entity_buffers);
                 }
             }
         }
 
-        if changes.dirt || changes.stone {
+        if changes.textures {
+            let texture_names = block_registry.texture_names();
+
             glw::bind_texture(glw::TEXTURE_2D_ARRAY, &self.texture_atlas_name);
+
+            // Reallocate because the number of textures may have changed.
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,                           // target
+                0,                                              // mipmap level
+                gl::RGBA8 as i32,                               // internal format
+                TEXTURE_SIZE as i32,                            // width
+                TEXTURE_SIZE as i32,                            // height
+                ::std::cmp::max(texture_names.len(), 1) as i32, // depth (layer count)
+                0,                                              // border (must be 0)
+                gl::RGBA,                                       // format
+                gl::UNSIGNED_BYTE,                              // type
+                ::std::ptr::null(),                             // data
+            );
+
+            for (layer, texture_name) in texture_names.iter().enumerate() {
+                let file_path = assets.block_texture_path(texture_name);
+                let img = match image::open(&file_path) {
+                    Ok(img) => img.flipv().to_rgba(),
+                    Err(err) => {
+                        eprintln!("Failed to read {}: {}", file_path.display(), err);
+                        continue;
+                    }
+                };
+                if img.width() != TEXTURE_SIZE || img.height() != TEXTURE_SIZE {
+                    eprintln!(
+                        "Expected {} to be {}x{} but it is {}x{}.",
+                        file_path.display(),
+                        TEXTURE_SIZE,
+                        TEXTURE_SIZE,
+                        img.width(),
+                        img.height()
+                    );
+                    continue;
+                }
+                gl::TexSubImage3D(
+                    gl::TEXTURE_2D_ARRAY,                          // target
+                    0,                                             // mipmap level
+                    0,                                             // xoffset
+                    0,                                             // yoffset,
+                    layer as i32,                                  // zoffset (slice),
+                    img.width() as i32,                            // width
+                    img.height() as i32,                           // height
+                    1,                                             // depth
+                    gl::RGBA,                                      // format
+                    gl::UNSIGNED_BYTE,                             // type
+                    img.as_ptr() as *const ::std::os::raw::c_void, // data
+                );
+            }
+
+            glw::generate_mipmap(glw::TEXTURE_2D_ARRAY);
         }
+    }
 
-        if changes.stone {
-            let img = image::open(&assets.stone_xyz_png).unwrap();
-            let img = img.flipv().to_rgba();
-            assert_eq!(img.width(), 32);
-            assert_eq!(img.height(), 32);
-            gl::TexSubImage3D(
-                gl::TEXTURE_2D_ARRAY,                          // target
-                0,                                             // mipmap level
-                0,                                             // xoffset
-                0,                                             // yoffset,
-                0,                                             // zoffset (slice),
-                img.width() as i32,                            // width
-                img.height() as i32,                           // height
-                1,                                             // depth
-                gl::RGBA,                                      // format
-                gl::UNSIGNED_BYTE,                             // type
-                img.as_ptr() as *const ::std::os::raw::c_void, // data
+    unsafe fn set_up_vertex_array(&self, buffers: &LayerBuffers) {
+        glw::bind_vertex_array(&buffers.vertex_array_name);
+        glw::bind_buffer(glw::ARRAY_BUFFER, &buffers.vertex_buffer_name);
+
+        if let Some(ref loc) = self.vs_ver_pos_loc {
+            gl::EnableVertexAttribArray(loc.as_u32());
+            gl::VertexAttribPointer(
+                loc.as_u32(),                               // index
+                3,                                          // size (component count)
+                gl::FLOAT,                                  // type (component type)
+                gl::FALSE,                                  // normalized
+                ::std::mem::size_of::<MeshVertex>() as i32, // stride
+                0 as *const ::std::os::raw::c_void,         // offset
             );
         }
 
-        if changes.dirt {
-            let img = image::open(&assets.dirt_xyz_png).unwrap();
-            let img = img.flipv().to_rgba();
-            assert_eq!(img.width(), 32);
-            assert_eq!(img.height(), 32);
-            gl::TexSubImage3D(
-                gl::TEXTURE_2D_ARRAY,                          // target
-                0,                                             // mipmap level
-                0,                                             // xoffset
-                0,                                             // yoffset,
-                1,                                             // zoffset (slice),
-                img.width() as i32,                            // width
-                img.height() as i32,                           // height
-                1,                                             // depth
-                gl::RGBA,                                      // format
-                gl::UNSIGNED_BYTE,                             // type
-                img.as_ptr() as *const ::std::os::raw::c_void, // data
+        if let Some(ref loc) = self.vs_tex_pos_loc {
+            gl::EnableVertexAttribArray(loc.as_u32());
+            gl::VertexAttribPointer(
+                loc.as_u32(),                                                           // index
+                2,                                          // size (component count)
+                gl::FLOAT,                                  // type (component type)
+                gl::FALSE,                                  // normalized
+                ::std::mem::size_of::<MeshVertex>() as i32, // stride
+                ::std::mem::size_of::<Vector3<f32>>() as *const ::std::os::raw::c_void, // offset
             );
         }
 
-        if changes.stone || changes.dirt {
-            glw::generate_mipmap(glw::TEXTURE_2D_ARRAY);
+        if let Some(ref loc) = self.vs_tex_layer_loc {
+            gl::EnableVertexAttribArray(loc.as_u32());
+            gl::VertexAttribIPointer(
+                loc.as_u32(),                               // index
+                1,                                          // size (component count)
+                gl::UNSIGNED_INT,                           // type (component type)
+                ::std::mem::size_of::<MeshVertex>() as i32, // stride
+                (::std::mem::size_of::<Vector3<f32>>() + ::std::mem::size_of::<Vector2<f32>>())
+                    as *const ::std::os::raw::c_void, // offset
+            );
         }
+
+        if let Some(ref loc) = self.vs_light_loc {
+            gl::EnableVertexAttribArray(loc.as_u32());
+            gl::VertexAttribPointer(
+                loc.as_u32(),                               // index
+                2,                                          // size (component count)
+                gl::FLOAT,                                  // type (component type)
+                gl::FALSE,                                  // normalized
+                ::std::mem::size_of::<MeshVertex>() as i32, // stride
+                (::std::mem::size_of::<Vector3<f32>>()
+                    + ::std::mem::size_of::<Vector2<f32>>()
+                    + ::std::mem::size_of::<u32>())
+                    as *const ::std::os::raw::c_void, // offset
+            );
+        }
+
+        if let Some(ref loc) = self.vs_ao_loc {
+            gl::EnableVertexAttribArray(loc.as_u32());
+            gl::VertexAttribPointer(
+                loc.as_u32(),                               // index
+                1,                                          // size (component count)
+                gl::FLOAT,                                  // type (component type)
+                gl::FALSE,                                  // normalized
+                ::std::mem::size_of::<MeshVertex>() as i32, // stride
+                (::std::mem::size_of::<Vector3<f32>>()
+                    + ::std::mem::size_of::<Vector2<f32>>()
+                    + ::std::mem::size_of::<u32>()
+                    + ::std::mem::size_of::<Vector2<f32>>())
+                    as *const ::std::os::raw::c_void, // offset
+            );
+        }
+    }
+
+    /// Replaces the GPU copy of a chunk's mesh.
+    pub unsafe fn update_chunk(&mut self, position_indices: Vector3<i32>, mesh: &ChunkMesh) {
+        if !self.chunk_buffers.contains_key(&position_indices) {
+            let mut layers = Vec::with_capacity(RENDER_LAYER_COUNT);
+            for _ in 0..RENDER_LAYER_COUNT {
+                let layer = LayerBuffers::new();
+                self.set_up_vertex_array(&layer);
+                layers.push(layer);
+            }
+            self.chunk_buffers.insert(
+                position_indices,
+                ChunkBuffers {
+                    layers,
+                    translucent_vertices: Vec::new(),
+                    sorted_for: None,
+                },
+            );
+        }
+
+        let buffers = self.chunk_buffers.get_mut(&position_indices).unwrap();
+        for &layer in RenderLayer::ALL.iter() {
+            buffers.layers[layer.index()].upload(mesh.layer(layer));
+        }
+        buffers.translucent_vertices = mesh.translucent_vertices.clone();
+        buffers.sorted_for = None;
     }
 
-    pub unsafe fn render(&mut self, pos_from_wld_to_clp_space: &Matrix4<f32>, chunk: &Chunk) {
+    /// Replaces the triangles of everything that is not part of a chunk,
+    /// given in world space.
+    pub unsafe fn update_entities(&mut self, vertices: &[MeshVertex]) {
+        self.entity_buffers.upload(vertices);
+    }
+
+    /// Frees the GPU copy of a chunk's mesh, if any.
+    pub unsafe fn remove_chunk(&mut self, position_indices: Vector3<i32>) {
+        if let Some(buffers) = self.chunk_buffers.remove(&position_indices) {
+            buffers.delete();
+        }
+    }
+
+    /// Number of triangles currently uploaded for all chunks.
+    pub fn triangle_count(&self) -> usize {
+        self.chunk_buffers
+            .values()
+            .flat_map(|buffers| buffers.layers.iter())
+            .map(|layer| layer.vertex_count / 3)
+            .sum()
+    }
+
+    /// Draws the chunks in the view frustrum for which `is_visible` returns
+    /// true. Opaque and cutout faces are drawn first. Translucent faces are
+    /// blended over them from back to front, without writing depth, so the
+    /// depth buffer only holds the opaque and cutout faces.
+    pub unsafe fn render<F>(
+        &mut self,
+        pos_from_wld_to_clp_space: &Matrix4<f32>,
+        camera_position: Vector3<f32>,
+        is_visible: F,
+    ) -> ChunkRenderStats
+    where
+        F: Fn(Vector3<i32>) -> bool,
+    {
+        let mut stats = ChunkRenderStats::default();
+        let frustrum_planes = FrustrumPlanes::from_matrix(pos_from_wld_to_clp_space);
+        let chunk_side = chunk::CHUNK_SIDE_BLOCKS as f32;
+
+        let mut visible = Vec::new();
+        for (&position_indices, buffers) in self.chunk_buffers.iter() {
+            if buffers.layers.iter().all(|layer| layer.vertex_count == 0) {
+                continue;
+            }
+            let chunk_min = position_indices.cast::<f32>().unwrap() * chunk_side;
+            let chunk_max = chunk_min + Vector3::from_value(chunk_side);
+            if !frustrum_planes.intersects_aabb(chunk_min, chunk_max) {
+                stats.culled += 1;
+                continue;
+            }
+            if !is_visible(position_indices) {
+                stats.occluded += 1;
+                continue;
+            }
+            stats.drawn += 1;
+            visible.push(position_indices);
+        }
+
+        // Translucent faces are drawn furthest chunk first, each sorted for
+        // the camera block so they only need sorting when it changes.
+        let chunk_center =
+            |p: Vector3<i32>| (p.cast::<f32>().unwrap() + Vector3::from_value(0.5)) * chunk_side;
+        visible.sort_by(|&a, &b| {
+            let da = (chunk_center(a) - camera_position).magnitude2();
+            let db = (chunk_center(b) - camera_position).magnitude2();
+            db.partial_cmp(&da).unwrap()
+        });
+        let camera_block = camera_position.map(|c| c.floor() as i32);
+        for &position_indices in visible.iter() {
+            let buffers = self.chunk_buffers.get_mut(&position_indices).unwrap();
+            if buffers.translucent_vertices.is_empty() || buffers.sorted_for == Some(camera_block) {
+                continue;
+            }
+            let chunk_min = position_indices.cast::<f32>().unwrap() * chunk_side;
+            mesher::sort_back_to_front(
+                &mut buffers.translucent_vertices,
+                camera_position - chunk_min,
+            );
+            buffers.layers[RenderLayer::Translucent.index()].upload(&buffers.translucent_vertices);
+            buffers.sorted_for = Some(camera_block);
+        }
+
         if let Program::Linked(ref program_name) = self.program {
             if let Some(ref pos_from_wld_to_clp_space_loc) = self.pos_from_wld_to_clp_space_loc {
-                // Update block type buffer.
-                glw::bind_buffer(glw::ARRAY_BUFFER, &self.block_buffer_name);
-                gl::BufferSubData(
-                    gl::ARRAY_BUFFER,                                                     // target
-                    0,                                                                    // offset
-                    ::std::mem::size_of::<[Block; chunk::CHUNK_TOTAL_BLOCKS]>() as isize, // size
-                    chunk.blocks.as_ptr() as *const ::std::os::raw::c_void,               // data
-                );
-
                 glw::use_program(&program_name);
 
-                glw::bind_vertex_array(&self.vertex_array_name);
-
-                // for (position, block) in chunk.blocks() {
-                //     match block {
-                //         Block::Void => continue,
-                //         Block::Stone => {
-                //             glw::active_texture(glw::TEXTURE0);
-                //             glw::bind_texture(glw::TEXTURE_2D, &self.stone_texture_name);
-                //         }
-                //         Block::Dirt => {
-                //             glw::active_texture(glw::TEXTURE0);
-                //             glw::bind_texture(glw::TEXTURE_2D, &self.dirt_texture_name);
-                //         }
-                //     }
-
-                //     let pos_from_obj_to_wld_space = Matrix4::from_translation(position);
-
-                //     unsafe {
-                //         let loc = gl::GetUniformLocation(
-                //             self.program_name.as_u32(),
-                //             gl_str!("pos_from_obj_to_clp_space"),
-                //         );
-                //         assert!(loc != -1, "failed to query uniform location");
-                //         let pos_from_obj_to_clp_space =
-                //             pos_from_wld_to_clp_space * pos_from_obj_to_wld_space;
-                //         gl::UniformMatrix4fv(
-                //             loc,                                // location
-                //             1,                                  // count
-                //             gl::FALSE,                          // row major
-                //             pos_from_obj_to_clp_space.as_ptr(), // data
-                //         );
-                //     }
-
-                //     unsafe {
-                //         gl::DrawElements(
-                //             gl::TRIANGLES,                      // mode
-                //             12 * 3,                             // count
-                //             gl::UNSIGNED_INT,                   // index type,
-                //             0 as *const ::std::os::raw::c_void, // offset
-                //         );
-                //     }
-                // }
-
                 glw::uniform_matrix4f(
                     pos_from_wld_to_clp_space_loc,
                     pos_from_wld_to_clp_space.as_matrix_ref(),
@@ -437,15 +524,63 @@ impl ChunkRenderer {
                 glw::active_texture(glw::TEXTURE0);
                 glw::bind_texture(glw::TEXTURE_2D_ARRAY, &self.texture_atlas_name);
 
-                gl::DrawElementsInstanced(
-                    gl::TRIANGLES,                         // mode
-                    (cube::ELEMENT_DATA.len() * 3) as i32, // count
-                    gl::UNSIGNED_INT,                      // index type
-                    0 as *const ::std::os::raw::c_void,    // offset
-                    chunk::CHUNK_TOTAL_BLOCKS as i32,      // primitive count
-                );
+                for &layer in RenderLayer::ALL.iter() {
+                    if layer == RenderLayer::Translucent {
+                        gl::Enable(gl::BLEND);
+                        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                        gl::DepthMask(gl::FALSE);
+                    }
+
+                    if let Some(ref loc) = self.render_layer_loc {
+                        glw::uniform_1i(loc, layer.index() as i32);
+                    }
+
+                    for &position_indices in visible.iter() {
+                        let buffers = &self.chunk_buffers[&position_indices].layers[layer.index()];
+                        if buffers.vertex_count == 0 {
+                            continue;
+                        }
+
+                        if let Some(ref loc) = self.pos_from_chk_to_wld_space_loc {
+                            let chunk_min = position_indices.cast::<f32>().unwrap() * chunk_side;
+                            let pos_from_chk_to_wld_space = Matrix4::from_translation(chunk_min);
+                            glw::uniform_matrix4f(loc, pos_from_chk_to_wld_space.as_matrix_ref());
+                        }
+
+                        glw::bind_vertex_array(&buffers.vertex_array_name);
+
+                        gl::DrawArrays(
+                            gl::TRIANGLES,               // mode
+                            0,                           // first
+                            buffers.vertex_count as i32, // count
+                        );
+                    }
+
+                    // Entities are alpha tested like cutout faces.
+                    if layer == RenderLayer::Cutout && self.entity_buffers.vertex_count > 0 {
+                        if let Some(ref loc) = self.pos_from_chk_to_wld_space_loc {
+                            let identity = Matrix4::from_scale(1.0);
+                            glw::uniform_matrix4f(loc, identity.as_matrix_ref());
+                        }
+
+                        glw::bind_vertex_array(&self.entity_buffers.vertex_array_name);
+
+                        gl::DrawArrays(
+                            gl::TRIANGLES,                           // mode
+                            0,                                       // first
+                            self.entity_buffers.vertex_count as i32, // count
+                        );
+                    }
+                }
+
+                // Restore the state other renderers expect. Depth writes
+                // also have to be enabled for the depth buffer to be cleared.
+                gl::DepthMask(gl::TRUE);
+                gl::Disable(gl::BLEND);
             }
         }
+
+        stats
     }
 
     pub unsafe fn delete(self) {
@@ -454,10 +589,8 @@ impl ChunkRenderer {
             fragment_shader,
             program,
             texture_atlas_name,
-            vertex_array_name,
-            vertex_buffer_name,
-            element_buffer_name,
-            block_buffer_name,
+            chunk_buffers,
+            entity_buffers,
             ..
         } = self;
 
@@ -465,9 +598,52 @@ impl ChunkRenderer {
         vertex_shader.delete();
         program.delete();
         glw::delete_textures_move([texture_atlas_name].wrap_all());
-        glw::delete_buffers_move(
-            [vertex_buffer_name, element_buffer_name, block_buffer_name].wrap_all(),
+        for (_, buffers) in chunk_buffers {
+            buffers.delete();
+        }
+        entity_buffers.delete();
+    }
+}
+
+impl LayerBuffers {
+    unsafe fn new() -> Self {
+        let [vertex_array_name] = glw::gen_vertex_arrays_move::<[_; 1]>()
+            .unwrap_all()
+            .unwrap();
+        let [vertex_buffer_name] = glw::gen_buffers_move::<[_; 1]>().unwrap_all().unwrap();
+        LayerBuffers {
+            vertex_array_name,
+            vertex_buffer_name,
+            vertex_count: 0,
+        }
+    }
+
+    unsafe fn upload(&mut self, vertices: &[MeshVertex]) {
+        glw::bind_buffer(glw::ARRAY_BUFFER, &self.vertex_buffer_name);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,                                                // target
+            (::std::mem::size_of::<MeshVertex>() * vertices.len()) as isize, // size
+            vertices.as_ptr() as *const ::std::os::raw::c_void,              // data
+            gl::STATIC_DRAW,                                                 // usage
         );
+        self.vertex_count = vertices.len();
+    }
+
+    unsafe fn delete(self) {
+        let LayerBuffers {
+            vertex_array_name,
+            vertex_buffer_name,
+            ..
+        } = self;
         glw::delete_vertex_arrays_move([vertex_array_name].wrap_all());
+        glw::delete_buffers_move([vertex_buffer_name].wrap_all());
+    }
+}
+
+impl ChunkBuffers {
+    unsafe fn delete(self) {
+        for layer in self.layers {
+            layer.delete();
+        }
     }
 }
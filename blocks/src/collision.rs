@@ -0,0 +1,297 @@
+use cgmath::*;
+
+/// Boxes closer to a block than this count as touching it. This keeps
+/// rounding errors from pushing boxes into blocks or making them catch on
+/// the blocks they rest against.
+const EPSILON: f32 = 1e-4;
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Aabb { min, max }
+    }
+
+    /// The box of the block at `position`.
+    pub fn block(position: Vector3<i32>) -> Self {
+        let min = position.cast::<f32>().unwrap();
+        Aabb::new(min, min + Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translate(&self, offset: Vector3<f32>) -> Self {
+        Aabb::new(self.min + offset, self.max + offset)
+    }
+
+    /// Whether the boxes overlap. Touching boxes do not.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] < other.max[axis] && other.min[axis] < self.max[axis])
+    }
+}
+
+/// How far a box moved and what stopped it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Movement {
+    pub displacement: Vector3<f32>,
+    /// Per axis, whether a block kept the box from moving the full distance.
+    pub blocked: [bool; 3],
+}
+
+/// The range of blocks a box from `min` to `max` overlaps along one axis.
+fn overlapped_blocks(min: f32, max: f32) -> ::std::ops::Range<i32> {
+    (min + EPSILON).floor() as i32..(max - EPSILON).ceil() as i32
+}
+
+/// How far the box can move along `axis` before it hits a block.
+/// `solid_height` tells how high each block stops movement above its
+/// bottom. Blocks the box already overlaps are ignored so a box that ends
+/// up inside a block can move out of it.
+fn sweep_axis<F>(aabb: &Aabb, axis: usize, distance: f32, solid_height: &F) -> f32
+where
+    F: Fn(Vector3<i32>) -> f32,
+{
+    if distance == 0.0 {
+        return 0.0;
+    }
+
+    let u_axis = (axis + 1) % 3;
+    let v_axis = (axis + 2) % 3;
+    // The highest top up to `limit` of the blocks in a layer that are in
+    // the way of the box. Moving sideways, the box passes over blocks whose
+    // top is below its bottom.
+    let layer_top = |layer: i32, limit: f32| {
+        let mut top: Option<f32> = None;
+        for u in overlapped_blocks(aabb.min[u_axis], aabb.max[u_axis]) {
+            for v in overlapped_blocks(aabb.min[v_axis], aabb.max[v_axis]) {
+                let mut p = Vector3::zero();
+                p[axis] = layer;
+                p[u_axis] = u;
+                p[v_axis] = v;
+                let height = solid_height(p);
+                let block_top = p.y as f32 + height;
+                if height > 0.0
+                    && block_top <= limit
+                    && (axis == 1 || block_top > aabb.min.y + EPSILON)
+                {
+                    top = Some(top.map_or(block_top, |top| top.max(block_top)));
+                }
+            }
+        }
+        top
+    };
+
+    if distance > 0.0 {
+        let first = (aabb.max[axis] - EPSILON).ceil() as i32;
+        let last = (aabb.max[axis] + distance).ceil() as i32 - 1;
+        for layer in first..=last {
+            if layer_top(layer, f32::INFINITY).is_some() {
+                return (layer as f32 - aabb.max[axis]).max(0.0);
+            }
+        }
+    } else if axis == 1 {
+        // Blocks lower than a full block can end below the bottom of the
+        // box in the layer it is in, so that layer is searched as well.
+        let first = (aabb.min.y + EPSILON).floor() as i32;
+        let last = (aabb.min.y + distance).floor() as i32;
+        for layer in (last..=first).rev() {
+            if let Some(top) = layer_top(layer, aabb.min.y + EPSILON) {
+                return (top - aabb.min.y).min(0.0).max(distance);
+            }
+        }
+    } else {
+        let first = (aabb.min[axis] + EPSILON).floor() as i32 - 1;
+        let last = (aabb.min[axis] + distance).floor() as i32;
+        for layer in (last..=first).rev() {
+            if layer_top(layer, f32::INFINITY).is_some() {
+                return (layer as f32 + 1.0 - aabb.min[axis]).min(0.0);
+            }
+        }
+    }
+    distance
+}
+
+/// Moves a box by `displacement` through a world where `solid_height`
+/// tells how high each block stops movement above its bottom, 0 for
+/// blocks that can be moved through. The box moves along y first and then
+/// along x and z, each time up to the first solid block, so it slides
+/// along whatever stops it. Every block the box sweeps through is checked,
+/// so it does not pass through thin walls however far it moves.
+pub fn sweep<F>(aabb: Aabb, displacement: Vector3<f32>, solid_height: F) -> Movement
+where
+    F: Fn(Vector3<i32>) -> f32,
+{
+    let mut aabb = aabb;
+    let mut movement = Movement {
+        displacement: Vector3::zero(),
+        blocked: [false; 3],
+    };
+    for &axis in [1, 0, 2].iter() {
+        let distance = sweep_axis(&aabb, axis, displacement[axis], &solid_height);
+        movement.displacement[axis] = distance;
+        movement.blocked[axis] = distance != displacement[axis];
+        aabb.min[axis] += distance;
+        aabb.max[axis] += distance;
+    }
+    movement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Full blocks at the given positions.
+    fn solid(blocks: &[(i32, i32, i32)]) -> HashMap<Vector3<i32>, f32> {
+        blocks
+            .iter()
+            .map(|&(x, y, z)| (Vector3::new(x, y, z), 1.0))
+            .collect()
+    }
+
+    fn height_in<'a>(blocks: &'a HashMap<Vector3<i32>, f32>) -> impl Fn(Vector3<i32>) -> f32 + 'a {
+        move |p| blocks.get(&p).cloned().unwrap_or(0.0)
+    }
+
+    fn unit_box(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new(
+            Vector3::new(x, y, z),
+            Vector3::new(x + 0.5, y + 0.5, z + 0.5),
+        )
+    }
+
+    fn assert_near(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn moves_freely_through_void() {
+        let blocks = solid(&[]);
+        let displacement = Vector3::new(3.0, -2.0, 0.5);
+        let movement = sweep(unit_box(0.25, 0.25, 0.25), displacement, height_in(&blocks));
+        assert_eq!(movement.displacement, displacement);
+        assert_eq!(movement.blocked, [false; 3]);
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        // A wall at x = 3.
+        let blocks = solid(&[(3, 0, -1), (3, 0, 0), (3, 0, 1), (3, 0, 2)]);
+        let movement = sweep(
+            unit_box(1.0, 0.25, 0.25),
+            Vector3::new(4.0, 0.0, 1.0),
+            height_in(&blocks),
+        );
+        assert_near(movement.displacement, Vector3::new(1.5, 0.0, 1.0));
+        assert_eq!(movement.blocked, [true, false, false]);
+
+        // Sliding along the wall while touching it does not catch on the
+        // seams between its blocks.
+        let aabb = unit_box(2.5, 0.25, -0.75);
+        let movement = sweep(aabb, Vector3::new(0.5, 0.0, 2.5), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(0.0, 0.0, 2.5));
+        assert_eq!(movement.blocked, [true, false, false]);
+    }
+
+    #[test]
+    fn rests_on_floors_and_walks_over_seams() {
+        let blocks = solid(&[(0, 0, 0), (1, 0, 0), (2, 0, 0)]);
+        let aabb = unit_box(0.25, 1.0, 0.25);
+        let movement = sweep(aabb, Vector3::new(2.0, -0.1, 0.0), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(2.0, 0.0, 0.0));
+        assert_eq!(movement.blocked, [false, true, false]);
+    }
+
+    #[test]
+    fn lands_on_and_walks_over_lower_blocks() {
+        // A slab half a block high next to a full block.
+        let mut blocks = solid(&[(1, 0, 0)]);
+        blocks.insert(Vector3::new(0, 0, 0), 0.5);
+        let aabb = unit_box(0.25, 2.0, 0.25);
+        let movement = sweep(aabb, Vector3::new(0.0, -2.0, 0.0), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(0.0, -1.5, 0.0));
+        assert_eq!(movement.blocked, [false, true, false]);
+
+        // Standing on the slab, the box does not catch on it but is stopped
+        // by the full block.
+        let aabb = unit_box(0.25, 0.5, 0.25);
+        let movement = sweep(aabb, Vector3::new(1.0, -0.1, 0.0), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(0.25, 0.0, 0.0));
+        assert_eq!(movement.blocked, [true, true, false]);
+
+        // Below the top of the slab, the box is stopped by its side.
+        let aabb = unit_box(-1.0, 0.0, 0.25);
+        let movement = sweep(aabb, Vector3::new(1.0, 0.0, 0.0), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(0.5, 0.0, 0.0));
+        assert_eq!(movement.blocked, [true, false, false]);
+    }
+
+    #[test]
+    fn handles_corners() {
+        // Moving diagonally into an inside corner stops on both axes.
+        let blocks = solid(&[(2, 0, 0), (2, 0, 1), (0, 0, 2), (1, 0, 2)]);
+        let movement = sweep(
+            unit_box(1.0, 0.25, 1.0),
+            Vector3::new(1.0, 0.0, 1.0),
+            height_in(&blocks),
+        );
+        assert_near(movement.displacement, Vector3::new(0.5, 0.0, 0.5));
+        assert_eq!(movement.blocked, [true, false, true]);
+
+        // Moving diagonally past an outside corner only clips the axis that
+        // runs into it. The box moves along x first and then hits the
+        // block along z.
+        let blocks = solid(&[(2, 0, 2)]);
+        let movement = sweep(
+            unit_box(1.0, 0.25, 1.0),
+            Vector3::new(1.0, 0.0, 1.0),
+            height_in(&blocks),
+        );
+        assert_near(movement.displacement, Vector3::new(1.0, 0.0, 0.5));
+        assert_eq!(movement.blocked, [false, false, true]);
+
+        // Passing a block while touching its side is not a collision.
+        let movement = sweep(
+            unit_box(1.0, 0.25, 1.5),
+            Vector3::new(2.0, 0.0, 0.0),
+            height_in(&blocks),
+        );
+        assert_eq!(movement.displacement, Vector3::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn does_not_tunnel_at_high_speed() {
+        let blocks = solid(&[(0, -50, 0), (-1, 0, 0)]);
+        let aabb = unit_box(0.25, 10.0, 0.25);
+        let movement = sweep(aabb, Vector3::new(0.0, -1000.0, 0.0), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(0.0, -59.0, 0.0));
+
+        // Also at negative coordinates.
+        let aabb = unit_box(50.25, 0.25, 0.25);
+        let movement = sweep(aabb, Vector3::new(-1000.0, 0.0, 0.0), height_in(&blocks));
+        assert_near(movement.displacement, Vector3::new(-50.25, 0.0, 0.0));
+        assert_eq!(movement.blocked, [true, false, false]);
+    }
+
+    #[test]
+    fn moves_out_of_blocks_it_is_inside_of() {
+        let blocks = solid(&[(0, 0, 0)]);
+        let movement = sweep(
+            unit_box(0.25, 0.25, 0.25),
+            Vector3::new(1.0, 0.0, 0.0),
+            height_in(&blocks),
+        );
+        assert_eq!(movement.displacement, Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn boxes_intersect_unless_separated_or_touching() {
+        let a = Aabb::block(Vector3::new(0, 0, 0));
+        assert!(a.intersects(&unit_box(0.75, 0.75, 0.75)));
+        assert!(!a.intersects(&unit_box(1.0, 0.25, 0.25)));
+        assert!(!a.intersects(&unit_box(-2.0, 0.25, 0.25)));
+    }
+}
@@ -1,218 +1,174 @@
 use cgmath::*;
 
-#[repr(C)]
-pub struct Vertex {
-    ver_pos: Vector3<f32>,
-    tex_pos: Vector2<f32>,
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Face {
+    NegX = 0,
+    PosX = 1,
+    NegY = 2,
+    PosY = 3,
+    NegZ = 4,
+    PosZ = 5,
 }
 
-pub static VERTEX_DATA: [Vertex; 4 * 6 - 4] = [
-    // 00: -X 00
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: -0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 0.0 },
-    },
-    // 01: -X 10
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: -0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 0.0 },
-    },
-    // 02: -X 01
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: 0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 1.0 },
-    },
-    // 03: -X 11
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: 0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 1.0 },
-    },
-    // 04: +X 00
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: -0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 0.0 },
-    },
-    // 05: +X 10
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: -0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 0.0 },
-    },
-    // 06: +X 01
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: 0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 1.0 },
-    },
-    // 07: +X 11
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: 0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 1.0 },
-    },
-    // 08: -Z 00
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: -0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 0.0 },
-    },
-    // 09: -Z 10
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: -0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 0.0 },
-    },
-    // 10: -Z 01
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: 0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 1.0 },
-    },
-    // 11: -Z 11
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: 0.5,
-            z: -0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 1.0 },
-    },
-    // 12: +Z 00
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: -0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 0.0 },
-    },
-    // 13: +Z 10
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: -0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 0.0 },
-    },
-    // 14: +Z 01
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: 0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 1.0 },
-    },
-    // 15: +Z 11
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: 0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 1.0 },
-    },
-    // 00: -Y 00 = -X 00
-    // 05: -Y 10 = +X 10
-    // 16: -Y 01
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: -0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 1.0 },
-    },
-    // 17: -Y 11
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: -0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 1.0 },
-    },
-    // 18: +Y 00
-    Vertex {
-        ver_pos: Vector3 {
-            x: -0.5,
-            y: 0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 0.0, y: 0.0 },
-    },
-    // 19: +Y 10
-    Vertex {
-        ver_pos: Vector3 {
-            x: 0.5,
-            y: 0.5,
-            z: 0.5,
-        },
-        tex_pos: Vector2 { x: 1.0, y: 0.0 },
-    },
-    // 02: +Y 01 = -X 01
-    // 07: +Y 11 = +X 11
-];
+pub const FACE_COUNT: usize = 6;
+
+impl Face {
+    pub const ALL: [Face; FACE_COUNT] = [
+        Face::NegX,
+        Face::PosX,
+        Face::NegY,
+        Face::PosY,
+        Face::NegZ,
+        Face::PosZ,
+    ];
+
+    #[inline]
+    pub fn from_axis(axis: usize, positive: bool) -> Face {
+        Face::ALL[axis * 2 + positive as usize]
+    }
+
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Index of the axis this face is perpendicular to.
+    #[inline]
+    pub fn axis(self) -> usize {
+        self as usize / 2
+    }
+
+    #[inline]
+    pub fn is_positive(self) -> bool {
+        self as usize % 2 == 1
+    }
+
+    #[inline]
+    pub fn opposite(self) -> Face {
+        Face::ALL[self as usize ^ 1]
+    }
+
+    #[inline]
+    pub fn normal(self) -> Vector3<i32> {
+        let mut n = Vector3::zero();
+        n[self.axis()] = if self.is_positive() { 1 } else { -1 };
+        n
+    }
+
+    /// Indices of the axes along which the texture coordinates u and v of
+    /// this face run.
+    #[inline]
+    pub fn tex_axes(self) -> (usize, usize) {
+        match self {
+            Face::NegX | Face::PosX => (2, 1),
+            Face::NegY | Face::PosY => (0, 2),
+            Face::NegZ | Face::PosZ => (0, 1),
+        }
+    }
+
+    /// The corners of this face on the unit cube [0, 1]^3, counter
+    /// clockwise when looking at the face from outside the cube.
+    #[inline]
+    pub fn corners(self) -> &'static [FaceCorner; 4] {
+        &FACE_CORNERS[self as usize]
+    }
+}
 
-#[repr(C)]
-pub struct Triangle(u32, u32, u32);
+#[derive(Clone, Copy, Debug)]
+pub struct FaceCorner {
+    pub ver_pos: Vector3<u8>,
+    pub tex_pos: Vector2<u8>,
+}
+
+macro_rules! corner {
+    ($x:expr, $y:expr, $z:expr; $u:expr, $v:expr) => {
+        FaceCorner {
+            ver_pos: Vector3 {
+                x: $x,
+                y: $y,
+                z: $z,
+            },
+            tex_pos: Vector2 { x: $u, y: $v },
+        }
+    };
+}
 
-pub static ELEMENT_DATA: [Triangle; 2 * 6] = [
+static FACE_CORNERS: [[FaceCorner; 4]; FACE_COUNT] = [
     // -X
-    Triangle(00, 01, 03),
-    Triangle(03, 02, 00),
+    [
+        corner!(0, 0, 0; 0, 0),
+        corner!(0, 0, 1; 1, 0),
+        corner!(0, 1, 1; 1, 1),
+        corner!(0, 1, 0; 0, 1),
+    ],
     // +X
-    Triangle(04, 05, 07),
-    Triangle(07, 06, 04),
-    // -Z
-    Triangle(08, 09, 11),
-    Triangle(11, 10, 08),
-    // +Z
-    Triangle(12, 13, 15),
-    Triangle(15, 14, 12),
+    [
+        corner!(1, 0, 1; 0, 0),
+        corner!(1, 0, 0; 1, 0),
+        corner!(1, 1, 0; 1, 1),
+        corner!(1, 1, 1; 0, 1),
+    ],
     // -Y
-    Triangle(00, 05, 17),
-    Triangle(17, 16, 00),
+    [
+        corner!(0, 0, 0; 0, 0),
+        corner!(1, 0, 0; 1, 0),
+        corner!(1, 0, 1; 1, 1),
+        corner!(0, 0, 1; 0, 1),
+    ],
     // +Y
-    Triangle(18, 19, 07),
-    Triangle(07, 02, 18),
+    [
+        corner!(0, 1, 1; 0, 0),
+        corner!(1, 1, 1; 1, 0),
+        corner!(1, 1, 0; 1, 1),
+        corner!(0, 1, 0; 0, 1),
+    ],
+    // -Z
+    [
+        corner!(1, 0, 0; 0, 0),
+        corner!(0, 0, 0; 1, 0),
+        corner!(0, 1, 0; 1, 1),
+        corner!(1, 1, 0; 0, 1),
+    ],
+    // +Z
+    [
+        corner!(0, 0, 1; 0, 0),
+        corner!(1, 0, 1; 1, 0),
+        corner!(1, 1, 1; 1, 1),
+        corner!(0, 1, 1; 0, 1),
+    ],
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_wind_counter_clockwise_around_normal() {
+        for &face in Face::ALL.iter() {
+            let c = face.corners();
+            let p0 = c[0].ver_pos.cast::<i32>().unwrap();
+            let p1 = c[1].ver_pos.cast::<i32>().unwrap();
+            let p3 = c[3].ver_pos.cast::<i32>().unwrap();
+            assert_eq!((p1 - p0).cross(p3 - p0), face.normal(), "{:?}", face);
+        }
+    }
+
+    #[test]
+    fn tex_axes_match_corners() {
+        for &face in Face::ALL.iter() {
+            let (u_axis, v_axis) = face.tex_axes();
+            let c = face.corners();
+            for i in 0..4 {
+                for j in 0..4 {
+                    let dp =
+                        c[j].ver_pos.cast::<i32>().unwrap() - c[i].ver_pos.cast::<i32>().unwrap();
+                    let dt =
+                        c[j].tex_pos.cast::<i32>().unwrap() - c[i].tex_pos.cast::<i32>().unwrap();
+                    assert_eq!(dp[u_axis].abs(), dt.x.abs(), "{:?}", face);
+                    assert_eq!(dp[v_axis].abs(), dt.y.abs(), "{:?}", face);
+                }
+            }
+        }
+    }
+}
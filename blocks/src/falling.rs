@@ -0,0 +1,194 @@
+use block::Block;
+use block_registry::BlockRegistry;
+use cgmath::*;
+use world::split_block_position;
+use world::World;
+
+/// Acceleration of falling blocks in blocks per second squared.
+pub const GRAVITY: f32 = 30.0;
+
+/// Falling blocks do not fall faster than this many blocks per second.
+pub const MAX_FALL_SPEED: f32 = 40.0;
+
+/// A block that lost its support. It falls straight down and turns back
+/// into a block when it lands on a solid block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FallingBlock {
+    pub block: Block,
+    /// Minimum corner of the block in world space. Only y changes while
+    /// falling.
+    pub position: Vector3<f32>,
+    /// Downward speed in blocks per second.
+    pub velocity: f32,
+}
+
+impl FallingBlock {
+    /// A block that starts falling from rest at `position`.
+    pub fn new(block: Block, position: Vector3<i32>) -> Self {
+        FallingBlock {
+            block,
+            position: position.cast::<f32>().unwrap(),
+            velocity: 0.0,
+        }
+    }
+
+    /// Moves the block down for `delta_time` seconds. Every block the bottom
+    /// passes is checked, so fast blocks can not fall through thin floors.
+    /// Returns where the block landed, if it did. A block waits above
+    /// chunks that are not loaded.
+    pub fn step(
+        &mut self,
+        world: &World,
+        registry: &BlockRegistry,
+        delta_time: f32,
+    ) -> Option<Vector3<i32>> {
+        let x = self.position.x.floor() as i32;
+        let z = self.position.z.floor() as i32;
+        let velocity = (self.velocity + GRAVITY * delta_time).min(MAX_FALL_SPEED);
+        let y = self.position.y - velocity * delta_time;
+
+        let mut below = self.position.y.ceil() as i32 - 1;
+        while below as f32 + 1.0 > y {
+            let p = Vector3::new(x, below, z);
+            if world.chunk_at(split_block_position(p).0).is_none() {
+                self.position.y = below as f32 + 1.0;
+                self.velocity = 0.0;
+                return None;
+            }
+            if registry.is_solid(world.block_at(p)) {
+                return Some(Vector3::new(x, below + 1, z));
+            }
+            below -= 1;
+        }
+
+        self.position.y = y;
+        self.velocity = velocity;
+        None
+    }
+}
+
+/// Moves all falling blocks in the world and places the ones that land.
+/// Returns the positions of the placed blocks.
+pub fn update(world: &mut World, registry: &BlockRegistry, delta_time: f32) -> Vec<Vector3<i32>> {
+    let mut landed = Vec::new();
+    for mut falling_block in world.take_falling_blocks() {
+        match falling_block.step(world, registry, delta_time) {
+            Some(position) => {
+                world.set_block(position, falling_block.block);
+                landed.push(position);
+            }
+            None => world.add_falling_block(falling_block),
+        }
+    }
+    landed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::Chunk;
+
+    const STONE: Block = Block(1);
+    const SAND: Block = Block(2);
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::parse("[stone]\n[sand]\nfalls = true", |_| true).unwrap()
+    }
+
+    /// Two chunks on top of each other with a one block thick floor at
+    /// y = 2.
+    fn floor_world() -> World {
+        let mut world = World::new();
+        world.insert_chunk(Chunk::new(Vector3::zero()));
+        world.insert_chunk(Chunk::new(Vector3::unit_y()));
+        for z in 0..16 {
+            for x in 0..16 {
+                world.set_block(Vector3::new(x, 2, z), STONE);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn accelerates_and_lands_on_the_floor() {
+        let registry = registry();
+        let mut world = floor_world();
+        world.add_falling_block(FallingBlock::new(SAND, Vector3::new(4, 30, 5)));
+
+        let dt = 1.0 / 100.0;
+        let mut previous_velocity = 0.0;
+        let mut steps = 0;
+        loop {
+            steps += 1;
+            assert!(steps < 1000);
+            if !update(&mut world, &registry, dt).is_empty() {
+                break;
+            }
+            let velocity = world.falling_blocks()[0].velocity;
+            assert!(velocity >= previous_velocity);
+            previous_velocity = velocity;
+        }
+        assert!(world.falling_blocks().is_empty());
+        // Falling 27 blocks takes about sqrt(2 * 27 / GRAVITY) seconds.
+        assert!(steps > 120 && steps < 150);
+        assert_eq!(world.block_at(Vector3::new(4, 3, 5)), SAND);
+        assert_eq!(world.block_at(Vector3::new(4, 30, 5)), Block::VOID);
+    }
+
+    #[test]
+    fn does_not_tunnel_through_thin_floors() {
+        let registry = registry();
+        let mut world = floor_world();
+        let mut falling_block = FallingBlock::new(SAND, Vector3::new(1, 31, 1));
+        falling_block.velocity = MAX_FALL_SPEED;
+        // One step moves the block well past the floor.
+        assert_eq!(
+            falling_block.step(&world, &registry, 1.0),
+            Some(Vector3::new(1, 3, 1))
+        );
+
+        // Landing on a block right below it.
+        world.set_block(Vector3::new(1, 10, 1), STONE);
+        let mut falling_block = FallingBlock::new(SAND, Vector3::new(1, 11, 1));
+        assert_eq!(
+            falling_block.step(&world, &registry, 0.1),
+            Some(Vector3::new(1, 11, 1))
+        );
+    }
+
+    #[test]
+    fn speed_is_limited() {
+        let registry = registry();
+        let world = floor_world();
+        let mut falling_block = FallingBlock::new(SAND, Vector3::new(1, 31, 1));
+        for _ in 0..10 {
+            assert_eq!(falling_block.step(&world, &registry, 0.01), None);
+        }
+        let velocity = falling_block.velocity;
+        assert!((velocity - 10.0 * 0.01 * GRAVITY).abs() < 1e-4);
+        falling_block.position.y = 31.0;
+        falling_block.velocity = MAX_FALL_SPEED;
+        falling_block.step(&world, &registry, 0.01);
+        assert_eq!(falling_block.velocity, MAX_FALL_SPEED);
+    }
+
+    #[test]
+    fn waits_above_unloaded_chunks() {
+        let registry = registry();
+        let mut world = World::new();
+        world.insert_chunk(Chunk::new(Vector3::unit_y()));
+        let mut falling_block = FallingBlock::new(SAND, Vector3::new(1, 20, 1));
+        for _ in 0..100 {
+            assert_eq!(falling_block.step(&world, &registry, 0.1), None);
+        }
+        assert_eq!(falling_block.position.y, 16.0);
+
+        world.insert_chunk(Chunk::new(Vector3::zero()));
+        world.set_block(Vector3::new(1, 0, 1), STONE);
+        let mut landed = None;
+        for _ in 0..100 {
+            landed = landed.or(falling_block.step(&world, &registry, 0.1));
+        }
+        assert_eq!(landed, Some(Vector3::new(1, 1, 1)));
+    }
+}
@@ -0,0 +1,409 @@
+use block::Block;
+use block_registry::BlockRegistry;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_SIDE_BLOCKS;
+use cube::Face;
+use std::collections::HashSet;
+use world::split_block_position;
+use world::World;
+
+/// The state of a fluid block is its distance to the nearest source, so 0
+/// for sources. Falling fluid has this bit set instead.
+pub const FALLING: u8 = 8;
+
+/// Largest distance a fluid can flow sideways from a source.
+pub const MAX_FLUID_SPREAD: u8 = FALLING - 1;
+
+/// Height of the surface of a source.
+const SOURCE_HEIGHT: f32 = 8.0 / 9.0;
+
+const SIDE_FACES: [Face; 4] = [Face::NegX, Face::PosX, Face::NegZ, Face::PosZ];
+
+#[inline]
+pub fn is_source(block: Block) -> bool {
+    block.state() == 0
+}
+
+#[inline]
+pub fn is_falling(block: Block) -> bool {
+    block.state() & FALLING != 0
+}
+
+/// Distance of fluid to the nearest source. Falling fluid lands as if it
+/// were a source.
+#[inline]
+fn distance(block: Block) -> u8 {
+    if is_falling(block) {
+        0
+    } else {
+        block.state()
+    }
+}
+
+/// Height of the surface of a fluid block from 0 to 1, not taking fluid
+/// above it into account.
+pub fn fluid_height(block: Block, spread: u8) -> f32 {
+    let spread = spread as f32;
+    SOURCE_HEIGHT * (spread + 1.0 - distance(block) as f32) / (spread + 1.0)
+}
+
+#[inline]
+fn is_loaded(world: &World, position: Vector3<i32>) -> bool {
+    world.chunk_at(split_block_position(position).0).is_some()
+}
+
+/// Whether fluid of type `id` can flow into the block at `position`.
+fn can_flow_into(world: &World, position: Vector3<i32>, id: usize) -> bool {
+    if !is_loaded(world, position) {
+        return false;
+    }
+    let block = world.block_at(position);
+    block == Block::VOID || block.id() == id && !is_source(block)
+}
+
+/// Fluid flows down first and only spreads sideways when it can not.
+fn spreads_sideways(world: &World, position: Vector3<i32>, id: usize) -> bool {
+    !can_flow_into(world, position - Vector3::unit_y(), id)
+}
+
+enum Next {
+    Unchanged,
+    /// The fluid is not updated during this step.
+    Wait,
+    Set(Block),
+}
+
+/// Moves fluids around one step at a time. Every step looks at the blocks
+/// that may change, decides what each of them becomes based on the world
+/// before the step and only then applies the changes. The result does not
+/// depend on the order in which blocks are visited, which keeps the
+/// simulation deterministic.
+///
+/// A block that is void or holds flowing fluid becomes:
+///
+/// 1. a source when at least two sources of the same fluid surround it and
+///    the block below is solid or a source,
+/// 2. falling fluid when there is fluid above it,
+/// 3. flowing fluid one block further from a source than its nearest
+///    neighbour that spreads sideways, if that is within the spread of the
+///    fluid, or void otherwise.
+///
+/// Fluids only flow through loaded chunks.
+pub struct FluidSimulation {
+    /// Blocks that may change during the next step.
+    active: HashSet<Vector3<i32>>,
+    step: u64,
+}
+
+impl FluidSimulation {
+    pub fn new() -> Self {
+        FluidSimulation {
+            active: HashSet::new(),
+            step: 0,
+        }
+    }
+
+    /// Makes the simulation look at a changed block and its neighbours.
+    pub fn wake(&mut self, position: Vector3<i32>) {
+        self.active.insert(position);
+        for &face in Face::ALL.iter() {
+            self.active.insert(position + face.normal());
+        }
+    }
+
+    /// Wakes all fluid blocks in a chunk, for example after loading it.
+    pub fn wake_chunk(&mut self, chunk: &Chunk, registry: &BlockRegistry) {
+        if chunk
+            .blocks
+            .palette()
+            .iter()
+            .all(|&b| registry.fluid(b).is_none())
+        {
+            return;
+        }
+        let origin = chunk.position_indices * CHUNK_SIDE_BLOCKS as i32;
+        for z in 0..CHUNK_SIDE_BLOCKS {
+            for y in 0..CHUNK_SIDE_BLOCKS {
+                for x in 0..CHUNK_SIDE_BLOCKS {
+                    if registry.fluid(*chunk.block_at(x, y, z)).is_some() {
+                        let p = Vector3::new(x, y, z).cast::<i32>().unwrap();
+                        self.wake(origin + p);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of blocks that may change during the next step.
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Advances the simulation by one step and returns the number of blocks
+    /// that changed.
+    pub fn step(&mut self, world: &mut World, registry: &BlockRegistry) -> usize {
+        self.step += 1;
+        let mut positions: Vec<Vector3<i32>> = self.active.drain().collect();
+        positions.sort_by_key(|p| (p.x, p.y, p.z));
+
+        let mut changes = Vec::new();
+        for position in positions {
+            if !is_loaded(world, position) {
+                continue;
+            }
+            match self.next_block(world, registry, position) {
+                Next::Unchanged => {}
+                Next::Wait => {
+                    self.active.insert(position);
+                }
+                Next::Set(block) => changes.push((position, block)),
+            }
+        }
+
+        for &(position, block) in changes.iter() {
+            world.set_block(position, block);
+            self.wake(position);
+        }
+        changes.len()
+    }
+
+    fn next_block(&self, world: &World, registry: &BlockRegistry, position: Vector3<i32>) -> Next {
+        let block = world.block_at(position);
+        match registry.fluid(block) {
+            Some(_) if is_source(block) => return Next::Unchanged,
+            Some(_) => {}
+            None if block == Block::VOID => {}
+            None => return Next::Unchanged,
+        }
+
+        let up = Vector3::unit_y();
+        let above = world.block_at(position + up);
+        let sides: Vec<(Vector3<i32>, Block)> = SIDE_FACES
+            .iter()
+            .map(|face| {
+                let side = position + face.normal();
+                (side, world.block_at(side))
+            })
+            .collect();
+
+        // Pick the fluid that flows here. Falling fluid wins, then the fluid
+        // already here, then the nearest fluid next to it.
+        let id = if registry.fluid(above).is_some() {
+            above.id()
+        } else if block != Block::VOID {
+            block.id()
+        } else {
+            let nearest = sides
+                .iter()
+                .filter(|&&(side, b)| {
+                    registry.fluid(b).is_some() && spreads_sideways(world, side, b.id())
+                })
+                .map(|&(_, b)| (distance(b), b.id()))
+                .min();
+            match nearest {
+                Some((_, id)) => id,
+                None => return Next::Unchanged,
+            }
+        };
+        let properties = registry.fluid(Block::new(id, 0)).unwrap();
+        if self.step % properties.delay as u64 != 0 {
+            return Next::Wait;
+        }
+
+        let source = Block::new(id, 0);
+        let below = world.block_at(position - up);
+        let source_count = sides.iter().filter(|&&(_, b)| b == source).count();
+        let next = if source_count >= 2 && (registry.is_solid(below) || below == source) {
+            source
+        } else if above.id() == id {
+            Block::new(id, FALLING)
+        } else {
+            let nearest = sides
+                .iter()
+                .filter(|&&(side, b)| b.id() == id && spreads_sideways(world, side, id))
+                .map(|&(_, b)| distance(b) + 1)
+                .min();
+            match nearest {
+                Some(distance) if distance <= properties.spread => Block::new(id, distance),
+                _ => Block::VOID,
+            }
+        };
+
+        if next == block {
+            Next::Unchanged
+        } else {
+            Next::Set(next)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: Block = Block(1);
+    const WATER: Block = Block(2);
+    const LAVA: Block = Block(3);
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::parse(
+            "[stone]\n[water]\nfluid = true\nfluid_delay = 1\n\
+             [lava]\nfluid = true\nfluid_delay = 3\nfluid_spread = 3",
+            |_| true,
+        )
+        .unwrap()
+    }
+
+    /// A single chunk with a stone floor at y = 0.
+    fn floor_world() -> World {
+        let mut world = World::new();
+        let mut chunk = Chunk::new(Vector3::zero());
+        for z in 0..16 {
+            for x in 0..16 {
+                *chunk.block_at_mut(x, 0, z) = STONE;
+            }
+        }
+        world.insert_chunk(chunk);
+        world
+    }
+
+    fn settle(simulation: &mut FluidSimulation, world: &mut World, registry: &BlockRegistry) {
+        for _ in 0..1000 {
+            simulation.step(world, registry);
+            if simulation.active_count() == 0 {
+                return;
+            }
+        }
+        panic!("The fluid did not settle.");
+    }
+
+    fn block_at(world: &World, x: i32, y: i32, z: i32) -> Block {
+        world.block_at(Vector3::new(x, y, z))
+    }
+
+    #[test]
+    fn spreads_with_decreasing_level() {
+        let registry = registry();
+        let mut world = floor_world();
+        let mut simulation = FluidSimulation::new();
+        world.set_block(Vector3::new(2, 1, 8), WATER);
+        simulation.wake(Vector3::new(2, 1, 8));
+        settle(&mut simulation, &mut world, &registry);
+
+        for x in 3..10 {
+            assert_eq!(block_at(&world, x, 1, 8), WATER.with_state(x as u8 - 2));
+        }
+        assert_eq!(block_at(&world, 10, 1, 8), Block::VOID);
+        assert_eq!(block_at(&world, 2, 1, 1), WATER.with_state(7));
+        assert_eq!(block_at(&world, 3, 1, 7), WATER.with_state(2));
+        // Nothing flows onto or into the floor.
+        assert_eq!(block_at(&world, 2, 2, 8), Block::VOID);
+        assert_eq!(block_at(&world, 2, 0, 8), STONE);
+    }
+
+    #[test]
+    fn flows_down_before_spreading() {
+        let registry = registry();
+        let mut world = floor_world();
+        let mut simulation = FluidSimulation::new();
+        world.set_block(Vector3::new(8, 10, 8), WATER);
+        simulation.wake(Vector3::new(8, 10, 8));
+        settle(&mut simulation, &mut world, &registry);
+
+        for y in 1..10 {
+            assert_eq!(block_at(&world, 8, y, 8), WATER.with_state(FALLING));
+        }
+        // Nothing spreads in the air, only on the floor.
+        assert_eq!(block_at(&world, 9, 10, 8), Block::VOID);
+        assert_eq!(block_at(&world, 9, 5, 8), Block::VOID);
+        assert_eq!(block_at(&world, 9, 1, 8), WATER.with_state(1));
+        assert_eq!(block_at(&world, 8, 1, 15), WATER.with_state(7));
+    }
+
+    #[test]
+    fn regenerates_sources_and_drains_without_them() {
+        let registry = registry();
+        let mut world = floor_world();
+        let mut simulation = FluidSimulation::new();
+        // A 1 block wide trench with a source at each end.
+        for x in 0..16 {
+            for z in 0..16 {
+                if z != 8 {
+                    world.set_block(Vector3::new(x, 1, z), STONE);
+                }
+            }
+        }
+        for &x in [5, 7].iter() {
+            world.set_block(Vector3::new(x, 1, 8), WATER);
+            simulation.wake(Vector3::new(x, 1, 8));
+        }
+        settle(&mut simulation, &mut world, &registry);
+        assert_eq!(block_at(&world, 6, 1, 8), WATER);
+        assert_eq!(block_at(&world, 4, 1, 8), WATER.with_state(1));
+
+        // Removing the sources drains the trench.
+        for x in 5..8 {
+            world.set_block(Vector3::new(x, 1, 8), Block::VOID);
+            simulation.wake(Vector3::new(x, 1, 8));
+        }
+        settle(&mut simulation, &mut world, &registry);
+        for x in 0..16 {
+            assert_eq!(block_at(&world, x, 1, 8), Block::VOID);
+        }
+    }
+
+    #[test]
+    fn slow_fluids_wait_between_updates() {
+        let registry = registry();
+        let mut world = floor_world();
+        let mut simulation = FluidSimulation::new();
+        world.set_block(Vector3::new(8, 1, 8), LAVA);
+        simulation.wake(Vector3::new(8, 1, 8));
+        assert_eq!(simulation.step(&mut world, &registry), 0);
+        assert_eq!(simulation.step(&mut world, &registry), 0);
+        assert_eq!(simulation.step(&mut world, &registry), 4);
+        settle(&mut simulation, &mut world, &registry);
+        assert_eq!(block_at(&world, 11, 1, 8), LAVA.with_state(3));
+        assert_eq!(block_at(&world, 12, 1, 8), Block::VOID);
+    }
+
+    #[test]
+    fn does_not_depend_on_wake_order() {
+        let registry = registry();
+        let run = |order: &[(i32, i32, i32)]| {
+            let mut world = floor_world();
+            let mut simulation = FluidSimulation::new();
+            for &(x, y, z) in order {
+                let p = Vector3::new(x, y, z);
+                world.set_block(p, if y > 3 { WATER } else { STONE });
+                simulation.wake(p);
+            }
+            for _ in 0..40 {
+                simulation.step(&mut world, &registry);
+            }
+            let chunk = world.chunk_at(Vector3::zero()).unwrap();
+            chunk.blocks.iter().collect::<Vec<_>>()
+        };
+        let edits = [(3, 9, 3), (6, 1, 4), (4, 1, 6), (12, 5, 12), (5, 2, 5)];
+        let mut reversed = edits.to_vec();
+        reversed.reverse();
+        assert_eq!(run(&edits), run(&reversed));
+    }
+
+    #[test]
+    fn surfaces_get_lower_away_from_sources() {
+        let spread = MAX_FLUID_SPREAD;
+        assert_eq!(fluid_height(WATER, spread), SOURCE_HEIGHT);
+        assert_eq!(
+            fluid_height(WATER.with_state(FALLING), spread),
+            SOURCE_HEIGHT
+        );
+        let mut previous = SOURCE_HEIGHT;
+        for distance in 1..=spread {
+            let height = fluid_height(WATER.with_state(distance), spread);
+            assert!(height < previous && height > 0.0);
+            previous = height;
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use cgmath::*;
+
 #[repr(C)]
 pub struct Frustrum {
     pub x0: f32,
@@ -8,6 +10,62 @@ pub struct Frustrum {
     pub z1: f32,
 }
 
+impl Frustrum {
+    /// Direction in camera space of the ray from the eye through a point
+    /// on the near plane. The point is given in normalized coordinates
+    /// where (0, 0) is the bottom left and (1, 1) the top right corner.
+    pub fn ray_direction(&self, p: Vector2<f32>) -> Vector3<f32> {
+        Vector3 {
+            x: self.x0 + p.x * (self.x1 - self.x0),
+            y: self.y0 + p.y * (self.y1 - self.y0),
+            z: -self.z0,
+        }
+    }
+}
+
+/// The planes bounding the view volume in world space. Each plane is
+/// stored as (a, b, c, d) where a * x + b * y + c * z + d >= 0 for points
+/// on the inside.
+#[derive(Clone, Copy, Debug)]
+pub struct FrustrumPlanes {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl FrustrumPlanes {
+    /// Extracts the planes from a world to clip space matrix. A point is
+    /// visible when -w <= x, y, z <= w in clip space, so every plane is
+    /// a sum or difference of the last row and one of the others.
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        let normalize = |p: Vector4<f32>| p / p.truncate().magnitude();
+        FrustrumPlanes {
+            planes: [
+                normalize(r3 + r0),
+                normalize(r3 - r0),
+                normalize(r3 + r1),
+                normalize(r3 - r1),
+                normalize(r3 + r2),
+                normalize(r3 - r2),
+            ],
+        }
+    }
+
+    /// Whether an axis aligned box may be visible. Boxes near the corners
+    /// of the frustrum can be reported as visible when they are not, but
+    /// visible boxes are never rejected.
+    pub fn intersects_aabb(&self, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal.
+            let p = Vector3 {
+                x: if plane.x >= 0.0 { max.x } else { min.x },
+                y: if plane.y >= 0.0 { max.y } else { min.y },
+                z: if plane.z >= 0.0 { max.z } else { min.z },
+            };
+            plane.truncate().dot(p) + plane.w >= 0.0
+        })
+    }
+}
+
 unsafe fn reinterpret<A, B>(a: &A) -> &B {
     assert_eq!(
         ::std::mem::size_of::<A>(),
@@ -23,3 +81,67 @@ impl AsRef<[f32; 6]> for Frustrum {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::Camera;
+
+    fn planes(camera: &Camera) -> FrustrumPlanes {
+        let pos_from_cam_to_clp_space = Matrix4::from(PerspectiveFov {
+            fovy: camera.fovy,
+            aspect: 1.0,
+            near: 0.2,
+            far: 100.0,
+        });
+        let pos_from_wld_to_cam_space = camera.pos_from_wld_to_cam_space();
+        FrustrumPlanes::from_matrix(&(pos_from_cam_to_clp_space * pos_from_wld_to_cam_space))
+    }
+
+    fn camera(position: Vector3<f32>, yaw: Deg<f32>) -> Camera {
+        Camera {
+            position,
+            yaw: Rad::from(yaw),
+            pitch: Rad(0.0),
+            fovy: Rad::from(Deg(90.0)),
+            positional_velocity: 0.0,
+            angular_velocity: 0.0,
+            zoom_velocity: 0.0,
+        }
+    }
+
+    fn unit_box(planes: &FrustrumPlanes, x: f32, y: f32, z: f32) -> bool {
+        let min = Vector3::new(x, y, z);
+        planes.intersects_aabb(min, min + Vector3::from_value(1.0))
+    }
+
+    #[test]
+    fn culls_boxes_outside_the_view() {
+        // Looking down the negative z axis.
+        let p = planes(&camera(Vector3::zero(), Deg(0.0)));
+        assert!(unit_box(&p, -0.5, -0.5, -10.0));
+        // Behind the camera.
+        assert!(!unit_box(&p, -0.5, -0.5, 10.0));
+        // Beyond the far plane.
+        assert!(!unit_box(&p, -0.5, -0.5, -110.0));
+        // Beside the 90 degree field of view and just inside it.
+        assert!(!unit_box(&p, 12.0, -0.5, -10.0));
+        assert!(unit_box(&p, 9.5, -0.5, -10.0));
+        assert!(!unit_box(&p, -0.5, -13.0, -10.0));
+    }
+
+    #[test]
+    fn keeps_boxes_around_the_camera() {
+        let p = planes(&camera(Vector3::new(0.5, 0.5, 0.5), Deg(0.0)));
+        assert!(unit_box(&p, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn follows_the_camera() {
+        // Turned 90 degrees to look down the positive x axis.
+        let p = planes(&camera(Vector3::new(100.0, 0.0, 0.0), Deg(90.0)));
+        assert!(unit_box(&p, 110.0, -0.5, -0.5));
+        assert!(!unit_box(&p, 90.0, -0.5, -0.5));
+        assert!(!unit_box(&p, 99.5, -0.5, -10.0));
+    }
+}
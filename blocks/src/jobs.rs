@@ -0,0 +1,436 @@
+use block_registry::BlockRegistry;
+use cgmath::*;
+use chunk::Chunk;
+use mesher;
+use mesher::ChunkMesh;
+use mesher::MeshOptions;
+use mesher::PaddedChunk;
+use occlusion::FaceConnectivity;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use storage::RegionStore;
+use structure::StructureGenerator;
+use terrain::TerrainGenerator;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum JobKind {
+    Load,
+    Mesh,
+}
+
+enum Job {
+    Load {
+        store: Arc<RegionStore>,
+        registry: Arc<BlockRegistry>,
+    },
+    Mesh {
+        padded: PaddedChunk,
+        registry: Arc<BlockRegistry>,
+        options: MeshOptions,
+    },
+}
+
+pub enum JobResult {
+    /// A chunk that was read from disk.
+    Loaded(Chunk),
+    /// A chunk that was not on disk and was generated instead.
+    Generated(Chunk),
+    /// A chunk that is on disk but could not be read. It is not generated
+    /// instead, so saving the chunk later can not replace what is on disk.
+    LoadFailed(Vector3<i32>, io::Error),
+    Meshed(Vector3<i32>, ChunkMesh, FaceConnectivity),
+}
+
+struct Task {
+    position_indices: Vector3<i32>,
+    ticket: u64,
+    cancelled: Arc<AtomicBool>,
+    job: Job,
+}
+
+struct Ticket {
+    ticket: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Runs chunk loading, generation and meshing on worker threads. There is
+/// at most one current job of each kind per chunk. Submitting a new job
+/// replaces the current one and the results of replaced and cancelled jobs
+/// are dropped, so only results that are still wanted come back.
+pub struct JobSystem {
+    task_tx: Option<mpsc::Sender<Task>>,
+    result_rx: mpsc::Receiver<(JobKind, u64, JobResult)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    next_ticket: u64,
+    current: HashMap<(JobKind, Vector3<i32>), Ticket>,
+}
+
+impl JobSystem {
+    pub fn new(
+        thread_count: usize,
+        terrain: Arc<TerrainGenerator>,
+        structures: Arc<StructureGenerator>,
+    ) -> Self {
+        let (task_tx, task_rx) = mpsc::channel::<Task>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let task_rx = Arc::new(Mutex::new(task_rx));
+
+        let workers = (0..thread_count)
+            .map(|i| {
+                let task_rx = Arc::clone(&task_rx);
+                let result_tx = result_tx.clone();
+                let terrain = Arc::clone(&terrain);
+                let structures = Arc::clone(&structures);
+                thread::Builder::new()
+                    .name(format!("worker {}", i))
+                    .spawn(move || loop {
+                        // Only hold the lock while waiting for a task.
+                        let task = match task_rx.lock().unwrap().recv() {
+                            Ok(task) => task,
+                            // The job system was dropped.
+                            Err(_) => break,
+                        };
+                        if task.cancelled.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let (kind, result) = match task.job {
+                            Job::Load { store, registry } => (
+                                JobKind::Load,
+                                load_or_generate(
+                                    task.position_indices,
+                                    &store,
+                                    &registry,
+                                    &terrain,
+                                    &structures,
+                                ),
+                            ),
+                            Job::Mesh {
+                                padded,
+                                registry,
+                                options,
+                            } => (
+                                JobKind::Mesh,
+                                JobResult::Meshed(
+                                    task.position_indices,
+                                    mesher::mesh(&padded, &registry, options),
+                                    FaceConnectivity::from_padded(&padded, &registry),
+                                ),
+                            ),
+                        };
+                        if result_tx.send((kind, task.ticket, result)).is_err() {
+                            break;
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        JobSystem {
+            task_tx: Some(task_tx),
+            result_rx,
+            workers,
+            next_ticket: 0,
+            current: HashMap::new(),
+        }
+    }
+
+    fn submit(&mut self, kind: JobKind, position_indices: Vector3<i32>, job: Job) {
+        self.cancel(kind, position_indices);
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.current.insert(
+            (kind, position_indices),
+            Ticket {
+                ticket,
+                cancelled: Arc::clone(&cancelled),
+            },
+        );
+        self.task_tx
+            .as_ref()
+            .unwrap()
+            .send(Task {
+                position_indices,
+                ticket,
+                cancelled,
+                job,
+            })
+            .expect("All workers stopped.");
+    }
+
+    /// Loads the chunk from `store` or generates it if it was never saved.
+    pub fn load(
+        &mut self,
+        position_indices: Vector3<i32>,
+        store: Arc<RegionStore>,
+        registry: Arc<BlockRegistry>,
+    ) {
+        self.submit(
+            JobKind::Load,
+            position_indices,
+            Job::Load { store, registry },
+        );
+    }
+
+    pub fn mesh(
+        &mut self,
+        position_indices: Vector3<i32>,
+        padded: PaddedChunk,
+        registry: Arc<BlockRegistry>,
+        options: MeshOptions,
+    ) {
+        self.submit(
+            JobKind::Mesh,
+            position_indices,
+            Job::Mesh {
+                padded,
+                registry,
+                options,
+            },
+        );
+    }
+
+    /// Whether a job of this kind is queued or running for the chunk.
+    pub fn is_busy(&self, kind: JobKind, position_indices: Vector3<i32>) -> bool {
+        self.current.contains_key(&(kind, position_indices))
+    }
+
+    /// Skips the job if it has not started and drops its result otherwise.
+    pub fn cancel(&mut self, kind: JobKind, position_indices: Vector3<i32>) {
+        if let Some(ticket) = self.current.remove(&(kind, position_indices)) {
+            ticket.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Cancels all jobs for the chunk.
+    pub fn cancel_all(&mut self, position_indices: Vector3<i32>) {
+        self.cancel(JobKind::Load, position_indices);
+        self.cancel(JobKind::Mesh, position_indices);
+    }
+
+    /// Number of jobs that are queued or running.
+    pub fn job_count(&self) -> usize {
+        self.current.len()
+    }
+
+    /// Takes the results of the jobs that finished since the last call.
+    pub fn finished(&mut self) -> Vec<JobResult> {
+        let mut results = Vec::new();
+        while let Ok((kind, ticket, result)) = self.result_rx.try_recv() {
+            let position_indices = match result {
+                JobResult::Loaded(ref chunk) | JobResult::Generated(ref chunk) => {
+                    chunk.position_indices
+                }
+                JobResult::LoadFailed(position_indices, _)
+                | JobResult::Meshed(position_indices, ..) => position_indices,
+            };
+            let key = (kind, position_indices);
+            let is_current = match self.current.get(&key) {
+                Some(current) => current.ticket == ticket,
+                None => false,
+            };
+            if is_current {
+                self.current.remove(&key);
+                results.push(result);
+            }
+        }
+        results
+    }
+}
+
+fn load_or_generate(
+    position_indices: Vector3<i32>,
+    store: &RegionStore,
+    registry: &BlockRegistry,
+    terrain: &TerrainGenerator,
+    structures: &StructureGenerator,
+) -> JobResult {
+    match store.load(position_indices, registry) {
+        Ok(Some(chunk)) => JobResult::Loaded(chunk),
+        Ok(None) => {
+            let mut chunk = terrain.generate(position_indices);
+            structures.populate(terrain, &mut chunk);
+            JobResult::Generated(chunk)
+        }
+        Err(err) => JobResult::LoadFailed(position_indices, err),
+    }
+}
+
+impl Drop for JobSystem {
+    fn drop(&mut self) {
+        for ticket in self.current.values() {
+            ticket.cancelled.store(true, Ordering::Relaxed);
+        }
+        // Closing the channel stops the workers once they are idle.
+        self.task_tx.take();
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use structure::StructureBlocks;
+    use terrain::TerrainBlocks;
+
+    fn job_system() -> JobSystem {
+        let terrain = TerrainGenerator::new(
+            42,
+            TerrainBlocks {
+                stone: Block(1),
+                dirt: Block(2),
+                cobblestone: Block(3),
+                sand: Block(4),
+            },
+        );
+        let structures = StructureGenerator::new(
+            42,
+            StructureBlocks {
+                cobblestone: Block(3),
+                planks: Block(5),
+                log: Block(6),
+                leaves: Block(7),
+            },
+        );
+        JobSystem::new(2, Arc::new(terrain), Arc::new(structures))
+    }
+
+    fn registry() -> Arc<BlockRegistry> {
+        let source = "[stone]\n[dirt]\n[cobblestone]\n[sand]\n[planks]\n[log]\n[leaves]";
+        Arc::new(BlockRegistry::parse(source, |_| true).unwrap())
+    }
+
+    /// A store in a fresh directory that is removed when the test ends.
+    struct TempStore(Arc<RegionStore>);
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let root = ::std::env::temp_dir().join(format!(
+                "blocks-jobs-test-{}-{}",
+                name,
+                ::std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&root);
+            TempStore(Arc::new(RegionStore::new(root)))
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.root);
+        }
+    }
+
+    fn wait_for_all(jobs: &mut JobSystem) -> Vec<JobResult> {
+        let start = Instant::now();
+        let mut results = Vec::new();
+        while jobs.job_count() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            results.extend(jobs.finished());
+            thread::sleep(Duration::from_millis(1));
+        }
+        results
+    }
+
+    #[test]
+    fn loads_saved_chunks_and_generates_the_others() {
+        let registry = registry();
+        let store = TempStore::new("load");
+        let mut saved = Chunk::new(Vector3::new(1, 0, 0));
+        saved.blocks.fill(Block(2));
+        store.0.save(&saved, &registry).unwrap();
+
+        let mut jobs = job_system();
+        for x in 0..4 {
+            jobs.load(
+                Vector3::new(x, 0, 0),
+                Arc::clone(&store.0),
+                Arc::clone(&registry),
+            );
+        }
+        let results = wait_for_all(&mut jobs);
+        assert_eq!(results.len(), 4);
+        for result in results {
+            match result {
+                JobResult::Loaded(chunk) => assert!(chunk.blocks == saved.blocks),
+                JobResult::Generated(chunk) => {
+                    assert!(chunk.position_indices.x < 4);
+                    assert!(chunk.position_indices.x != 1);
+                }
+                JobResult::LoadFailed(..) | JobResult::Meshed(..) => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn does_not_generate_chunks_that_could_not_be_read() {
+        let registry = registry();
+        let store = TempStore::new("corrupt");
+        store
+            .0
+            .save(&Chunk::new(Vector3::zero()), &registry)
+            .unwrap();
+        // Cut the region file short.
+        for entry in fs::read_dir(&store.0.root).unwrap() {
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .open(entry.unwrap().path())
+                .unwrap();
+            file.set_len(10).unwrap();
+        }
+
+        let mut jobs = job_system();
+        jobs.load(Vector3::zero(), Arc::clone(&store.0), Arc::clone(&registry));
+        match wait_for_all(&mut jobs).pop() {
+            Some(JobResult::LoadFailed(position_indices, _)) => {
+                assert_eq!(position_indices, Vector3::zero())
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn drops_results_of_cancelled_and_replaced_jobs() {
+        let registry = registry();
+        let store = TempStore::new("cancel");
+        let mut jobs = job_system();
+        let load = |jobs: &mut JobSystem, x: i32| {
+            jobs.load(
+                Vector3::new(x, 0, 0),
+                Arc::clone(&store.0),
+                Arc::clone(&registry),
+            );
+        };
+        for x in 0..8 {
+            load(&mut jobs, x);
+        }
+        for x in 0..8 {
+            if x % 2 == 0 {
+                jobs.cancel_all(Vector3::new(x, 0, 0));
+            }
+        }
+        // Replacing a job only returns the result of the new one.
+        load(&mut jobs, 1);
+        assert!(jobs.is_busy(JobKind::Load, Vector3::new(1, 0, 0)));
+        assert!(!jobs.is_busy(JobKind::Load, Vector3::new(2, 0, 0)));
+
+        let mut xs: Vec<i32> = wait_for_all(&mut jobs)
+            .into_iter()
+            .map(|result| match result {
+                JobResult::Generated(chunk) => chunk.position_indices.x,
+                _ => panic!(),
+            })
+            .collect();
+        xs.sort();
+        assert_eq!(xs, vec![1, 3, 5, 7]);
+    }
+}
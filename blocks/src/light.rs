@@ -0,0 +1,438 @@
+use block_registry::BlockRegistry;
+use block_registry::MAX_LIGHT;
+use cgmath::*;
+use chunk::CHUNK_SIDE_BLOCKS;
+use cube::Face;
+use std::collections::VecDeque;
+use world::World;
+
+/// Sky light in the high and block light in the low four bits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Light(pub u8);
+
+impl Light {
+    pub const DARK: Light = Light(0);
+
+    #[inline]
+    pub fn new(sky: u8, block: u8) -> Self {
+        debug_assert!(sky <= MAX_LIGHT && block <= MAX_LIGHT);
+        Light(sky << 4 | block)
+    }
+
+    /// Light coming from the sky, which is at `MAX_LIGHT` in the open.
+    #[inline]
+    pub fn sky(self) -> u8 {
+        self.0 >> 4
+    }
+
+    /// Light coming from blocks that emit light.
+    #[inline]
+    pub fn block(self) -> u8 {
+        self.0 & 0xf
+    }
+
+    #[inline]
+    fn get(self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => self.sky(),
+            Channel::Block => self.block(),
+        }
+    }
+
+    #[inline]
+    fn with(self, channel: Channel, level: u8) -> Self {
+        match channel {
+            Channel::Sky => Light::new(level, self.block()),
+            Channel::Block => Light::new(self.sky(), level),
+        }
+    }
+}
+
+/// Sky light and block light spread independently.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+const CHANNELS: [Channel; 2] = [Channel::Sky, Channel::Block];
+
+#[inline]
+fn level_at(world: &World, position: Vector3<i32>, channel: Channel) -> Option<u8> {
+    world.light_at(position).map(|light| light.get(channel))
+}
+
+#[inline]
+fn set_level_at(world: &mut World, position: Vector3<i32>, channel: Channel, level: u8) {
+    if let Some(light) = world.light_at(position) {
+        world.set_light(position, light.with(channel, level));
+    }
+}
+
+/// The level light at `level` has after moving one block through `face`.
+/// Sky light at full strength goes straight down without getting weaker.
+#[inline]
+fn spread_level(channel: Channel, face: Face, level: u8) -> u8 {
+    if channel == Channel::Sky && face == Face::NegY && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+/// Spreads light from the queued blocks to their neighbours until nothing
+/// gets brighter. Light does not enter opaque blocks or chunks that are not
+/// loaded.
+fn spread(
+    world: &mut World,
+    registry: &BlockRegistry,
+    channel: Channel,
+    queue: &mut VecDeque<Vector3<i32>>,
+) {
+    while let Some(p) = queue.pop_front() {
+        let level = match level_at(world, p, channel) {
+            Some(level) if level > 1 => level,
+            _ => continue,
+        };
+        for &face in Face::ALL.iter() {
+            let q = p + face.normal();
+            let new_level = spread_level(channel, face, level);
+            match level_at(world, q, channel) {
+                Some(current) if current < new_level => {}
+                _ => continue,
+            }
+            if registry.is_opaque(world.block_at(q)) {
+                continue;
+            }
+            set_level_at(world, q, channel, new_level);
+            queue.push_back(q);
+        }
+    }
+}
+
+/// Darkens the given blocks along with all blocks that got their light
+/// through them. Returns the blocks bordering the darkened area that still
+/// have light, from which the light has to be spread again.
+fn remove(
+    world: &mut World,
+    registry: &BlockRegistry,
+    channel: Channel,
+    seeds: Vec<(Vector3<i32>, u8)>,
+) -> VecDeque<Vector3<i32>> {
+    let mut queue = VecDeque::new();
+    let mut relight = VecDeque::new();
+    for (p, level) in seeds {
+        set_level_at(world, p, channel, 0);
+        queue.push_back((p, level));
+    }
+    while let Some((p, level)) = queue.pop_front() {
+        for &face in Face::ALL.iter() {
+            let q = p + face.normal();
+            let current = match level_at(world, q, channel) {
+                Some(current) if current > 0 => current,
+                _ => continue,
+            };
+            if current < level || spread_level(channel, face, level) == MAX_LIGHT {
+                set_level_at(world, q, channel, 0);
+                queue.push_back((q, current));
+                if channel == Channel::Block {
+                    // Blocks that emit light keep their own light.
+                    let emitted = registry.light(world.block_at(q));
+                    if emitted > 0 {
+                        set_level_at(world, q, channel, emitted);
+                        relight.push_back(q);
+                    }
+                }
+            } else {
+                relight.push_back(q);
+            }
+        }
+    }
+    relight
+}
+
+/// Updates the light after the block at `position` changed.
+fn update_block(world: &mut World, registry: &BlockRegistry, position: Vector3<i32>) {
+    let block = world.block_at(position);
+    for &channel in CHANNELS.iter() {
+        let level = match level_at(world, position, channel) {
+            Some(level) => level,
+            None => return,
+        };
+        let mut relight = if level > 0 {
+            remove(world, registry, channel, vec![(position, level)])
+        } else {
+            VecDeque::new()
+        };
+
+        if channel == Channel::Block && registry.light(block) > 0 {
+            set_level_at(world, position, channel, registry.light(block));
+            relight.push_back(position);
+        }
+        if !registry.is_opaque(block) {
+            // Let the light of the neighbours flow back in.
+            for &face in Face::ALL.iter() {
+                let q = position + face.normal();
+                if level_at(world, q, channel).map_or(false, |level| level > 0) {
+                    relight.push_back(q);
+                }
+            }
+            let above = position + Face::PosY.normal();
+            if channel == Channel::Sky && level_at(world, above, channel).is_none() {
+                set_level_at(world, position, channel, MAX_LIGHT);
+                relight.push_back(position);
+            }
+        }
+        spread(world, registry, channel, &mut relight);
+    }
+}
+
+/// Computes the light of a newly inserted chunk and lets it spread into its
+/// neighbours and theirs into it. Sky light comes in from above when the
+/// chunk above is not loaded.
+fn light_chunk(world: &mut World, registry: &BlockRegistry, position_indices: Vector3<i32>) {
+    let side = CHUNK_SIDE_BLOCKS as i32;
+    let origin = position_indices * side;
+    match world.chunk_at_mut(position_indices) {
+        Some(chunk) => {
+            for light in chunk.light.iter_mut() {
+                *light = Light::DARK;
+            }
+        }
+        None => return,
+    }
+
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+
+    for z in 0..side {
+        for x in 0..side {
+            let above = origin + Vector3::new(x, side, z);
+            let open = level_at(world, above, Channel::Sky).map_or(true, |l| l == MAX_LIGHT);
+            if !open {
+                continue;
+            }
+            for y in (0..side).rev() {
+                let p = origin + Vector3::new(x, y, z);
+                if registry.is_opaque(world.block_at(p)) {
+                    break;
+                }
+                set_level_at(world, p, Channel::Sky, MAX_LIGHT);
+                sky.push_back(p);
+            }
+        }
+    }
+
+    for z in 0..side {
+        for y in 0..side {
+            for x in 0..side {
+                let p = origin + Vector3::new(x, y, z);
+                let emitted = registry.light(world.block_at(p));
+                if emitted > 0 {
+                    set_level_at(world, p, Channel::Block, emitted);
+                    block.push_back(p);
+                }
+            }
+        }
+    }
+
+    // The blocks of the neighbours touching this chunk spread into it.
+    for &face in Face::ALL.iter() {
+        let (u_axis, v_axis) = face.tex_axes();
+        let axis = face.axis();
+        for v in 0..side {
+            for u in 0..side {
+                let mut p = origin;
+                p[u_axis] += u;
+                p[v_axis] += v;
+                p[axis] += if face.is_positive() { side } else { -1 };
+                if let Some(light) = world.light_at(p) {
+                    if light.sky() > 0 {
+                        sky.push_back(p);
+                    }
+                    if light.block() > 0 {
+                        block.push_back(p);
+                    }
+                }
+            }
+        }
+    }
+
+    spread(world, registry, Channel::Sky, &mut sky);
+    spread(world, registry, Channel::Block, &mut block);
+
+    // The chunk below may have been lit as if it was open to the sky.
+    let mut seeds = Vec::new();
+    for z in 0..side {
+        for x in 0..side {
+            let below = origin + Vector3::new(x, -1, z);
+            let bottom = origin + Vector3::new(x, 0, z);
+            if level_at(world, below, Channel::Sky) == Some(MAX_LIGHT)
+                && level_at(world, bottom, Channel::Sky) != Some(MAX_LIGHT)
+            {
+                seeds.push((below, MAX_LIGHT));
+            }
+        }
+    }
+    if !seeds.is_empty() {
+        let mut relight = remove(world, registry, Channel::Sky, seeds);
+        spread(world, registry, Channel::Sky, &mut relight);
+    }
+}
+
+/// Brings the light up to date with the chunks inserted and the blocks
+/// changed since the last update.
+pub fn update(world: &mut World, registry: &BlockRegistry) {
+    for position_indices in world.take_unlit_chunks() {
+        light_chunk(world, registry, position_indices);
+    }
+    for position in world.take_block_changes() {
+        update_block(world, registry, position);
+    }
+}
+
+/// Computes the light of every loaded chunk from scratch, for example
+/// after the light emitted by blocks changed.
+pub fn relight_all(world: &mut World, registry: &BlockRegistry) {
+    world.take_unlit_chunks();
+    world.take_block_changes();
+    let chunks: Vec<Vector3<i32>> = world.chunks().map(|c| c.position_indices).collect();
+    for &position_indices in chunks.iter() {
+        for light in world
+            .chunk_at_mut(position_indices)
+            .unwrap()
+            .light
+            .iter_mut()
+        {
+            *light = Light::DARK;
+        }
+    }
+    world.mark_all_chunks_dirty();
+    for position_indices in chunks {
+        light_chunk(world, registry, position_indices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use chunk::Chunk;
+    use noise;
+
+    const STONE: Block = Block(1);
+    const LAMP: Block = Block(2);
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::parse(
+            "[stone]\ntexture = stone_xyz.png\n[lamp]\ntexture = stone_xyz.png\nlight = 15",
+            |_| true,
+        )
+        .unwrap()
+    }
+
+    fn sky_at(world: &World, x: i32, y: i32, z: i32) -> u8 {
+        world.light_at(Vector3::new(x, y, z)).unwrap().sky()
+    }
+
+    fn block_light_at(world: &World, x: i32, y: i32, z: i32) -> u8 {
+        world.light_at(Vector3::new(x, y, z)).unwrap().block()
+    }
+
+    #[test]
+    fn packs_sky_and_block_light() {
+        let light = Light::new(15, 3);
+        assert_eq!(light.sky(), 15);
+        assert_eq!(light.block(), 3);
+        assert_eq!(light.with(Channel::Block, 7), Light::new(15, 7));
+        assert_eq!(Light::DARK.with(Channel::Sky, 1), Light::new(1, 0));
+    }
+
+    #[test]
+    fn sky_light_falls_under_an_overhang() {
+        let registry = registry();
+        let mut world = World::new();
+        // A roof over x < 8 at the top of the chunk.
+        let mut chunk = Chunk::new(Vector3::zero());
+        for z in 0..16 {
+            for x in 0..8 {
+                *chunk.block_at_mut(x, 15, z) = STONE;
+            }
+        }
+        world.insert_chunk(chunk);
+        update(&mut world, &registry);
+
+        assert_eq!(sky_at(&world, 8, 0, 5), MAX_LIGHT);
+        assert_eq!(sky_at(&world, 7, 0, 5), MAX_LIGHT - 1);
+        assert_eq!(sky_at(&world, 0, 3, 5), MAX_LIGHT - 8);
+        assert_eq!(sky_at(&world, 0, 15, 5), 0);
+    }
+
+    #[test]
+    fn block_light_falls_off_with_distance() {
+        let registry = registry();
+        let mut world = World::new();
+        // A dark cave inside two chunks of stone.
+        for x in 0..2 {
+            let mut chunk = Chunk::new(Vector3::new(x, 0, 0));
+            chunk.blocks.fill(STONE);
+            world.insert_chunk(chunk);
+        }
+        for x in 4..28 {
+            world.set_block(Vector3::new(x, 8, 8), Block::VOID);
+        }
+        world.set_block(Vector3::new(4, 8, 8), LAMP);
+        update(&mut world, &registry);
+
+        assert_eq!(block_light_at(&world, 4, 8, 8), 15);
+        assert_eq!(block_light_at(&world, 5, 8, 8), 14);
+        assert_eq!(block_light_at(&world, 18, 8, 8), 1);
+        assert_eq!(block_light_at(&world, 19, 8, 8), 0);
+        assert_eq!(sky_at(&world, 10, 8, 8), 0);
+
+        // Removing the lamp darkens the cave again.
+        world.set_block(Vector3::new(4, 8, 8), Block::VOID);
+        update(&mut world, &registry);
+        assert_eq!(block_light_at(&world, 5, 8, 8), 0);
+    }
+
+    #[test]
+    fn incremental_updates_match_lighting_from_scratch() {
+        let registry = registry();
+        let mut world = World::new();
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    world.insert_chunk(Chunk::new(Vector3::new(x, y, z)));
+                }
+            }
+        }
+        update(&mut world, &registry);
+
+        for n in 0..300 {
+            let h = noise::hash(7, n, 0, 0);
+            let p = Vector3::new((h & 31) as i32, (h >> 5 & 31) as i32, (h >> 10 & 31) as i32);
+            let block = match h >> 15 & 7 {
+                0 => LAMP,
+                1 | 2 => Block::VOID,
+                _ => STONE,
+            };
+            world.set_block(p, block);
+            if n % 50 == 49 {
+                update(&mut world, &registry);
+
+                let mut fresh = World::new();
+                for chunk in world.chunks() {
+                    let mut copy = Chunk::new(chunk.position_indices);
+                    copy.blocks = chunk.blocks.clone();
+                    fresh.insert_chunk(copy);
+                }
+                update(&mut fresh, &registry);
+                for chunk in world.chunks() {
+                    let expected = fresh.chunk_at(chunk.position_indices).unwrap();
+                    assert!(chunk.light == expected.light);
+                }
+            }
+        }
+    }
+}
@@ -20,63 +20,95 @@ extern crate image;
 extern crate notify;
 
 pub mod assets;
+pub mod behaviour;
+pub mod biome;
 pub mod block;
+pub mod block_registry;
 pub mod camera;
 pub mod cgmath_ext;
 pub mod chunk;
 pub mod chunk_renderer;
+pub mod collision;
 pub mod console;
 pub mod cube;
+pub mod falling;
+pub mod fluid;
 pub mod frustrum;
+pub mod jobs;
+pub mod light;
+pub mod mesher;
+pub mod noise;
+pub mod occlusion;
+pub mod palette;
+pub mod player;
 pub mod post_renderer;
 pub mod program;
 pub mod rate_counter;
+pub mod raycast;
 pub mod renderer;
+pub mod selection_renderer;
 pub mod shader;
+pub mod storage;
+pub mod streaming;
+pub mod structure;
+pub mod terrain;
 pub mod text_renderer;
+pub mod tick;
+pub mod world;
 
 use block::Block;
+use block_registry::BlockRegistry;
 use cgmath::*;
-use chunk::Chunk;
 use chunk::CHUNK_SIDE_BLOCKS;
-use chunk::CHUNK_TOTAL_BLOCKS;
 use chunk_renderer::ChunkRenderer;
 use chunk_renderer::ChunkRendererChanges;
+use collision::Aabb;
+use fluid::FluidSimulation;
 use frustrum::Frustrum;
+use frustrum::FrustrumPlanes;
 use glutin::GlContext;
 use glw::prelude::*;
+use jobs::JobKind;
+use jobs::JobResult;
+use jobs::JobSystem;
+use light::Light;
+use mesher::MeshMode;
+use mesher::MeshOptions;
+use mesher::PaddedChunk;
 use notify::Watcher;
+use occlusion::OcclusionCuller;
+use player::Player;
 use post_renderer::PostRenderer;
 use post_renderer::PostRendererChanges;
+use selection_renderer::SelectionRenderer;
+use selection_renderer::SelectionRendererChanges;
 use std::env;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::time::Duration;
 use std::{thread, time};
+use storage::RegionStore;
+use streaming::ChunkStreamer;
+use structure::StructureBlocks;
+use structure::StructureGenerator;
+use terrain::TerrainBlocks;
+use terrain::TerrainGenerator;
 use text_renderer::TextRenderer;
 use text_renderer::TextRendererChanges;
+use tick::TickScheduler;
+use world::split_block_position;
+use world::World;
+
+/// Loads the block types and gives them their behaviour over time.
+fn load_block_registry(path: &Path) -> Result<BlockRegistry, String> {
+    let mut registry = BlockRegistry::load(path)?;
+    behaviour::register(&mut registry);
+    Ok(registry)
+}
 
 fn main() {
-    let mut chunk = Chunk {
-        blocks: [Block::Void; CHUNK_TOTAL_BLOCKS],
-        position_indices: Vector3 { x: 0, y: 0, z: 0 },
-    };
-
-    for z in 0..CHUNK_SIDE_BLOCKS {
-        for x in 0..CHUNK_SIDE_BLOCKS {
-            *chunk.block_at_mut(x, 0, z) = Block::Stone;
-        }
-    }
-
-    for z in 5..CHUNK_SIDE_BLOCKS {
-        for x in 10..13 {
-            *chunk.block_at_mut(x, 1, z) = Block::Dirt;
-        }
-    }
-
-    *chunk.block_at_mut(5, 10, 1) = Block::Stone;
-    *chunk.block_at_mut(5, 10, 2) = Block::Dirt;
-
     let mut viewport = glw::Viewport::new(1024, 768);
 
     let mut events_loop = glutin::EventsLoop::new();
@@ -106,6 +138,58 @@ fn main() {
         env::var_os("BLOCKS_ASSET_DIR").map_or_else(|| PathBuf::from("assets"), PathBuf::from),
     );
 
+    // Shared with the worker threads that build meshes.
+    let mut block_registry = Arc::new(
+        load_block_registry(&assets.blocks_txt).unwrap_or_else(|err| panic!("{}", err)),
+    );
+
+    let seed = env::var("BLOCKS_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(0);
+
+    let terrain_generator = Arc::new(TerrainGenerator::new(
+        seed,
+        TerrainBlocks::from_registry(&block_registry).unwrap_or_else(|err| panic!("{}", err)),
+    ));
+
+    let structure_generator = Arc::new(StructureGenerator::new(
+        seed,
+        StructureBlocks::from_registry(&block_registry).unwrap_or_else(|err| panic!("{}", err)),
+    ));
+
+    // Shared with the worker threads that load chunks.
+    let region_store = Arc::new(RegionStore::new(
+        env::var_os("BLOCKS_WORLD_DIR").map_or_else(|| PathBuf::from("world"), PathBuf::from),
+    ));
+
+    let mut world = World::new();
+
+    // Radius in chunks around the camera in which chunks are loaded.
+    let view_distance = env::var("BLOCKS_VIEW_DISTANCE")
+        .ok()
+        .and_then(|distance| distance.parse().ok())
+        .unwrap_or(6);
+
+    let mut chunk_streamer = ChunkStreamer::new(view_distance, view_distance + 2, 8);
+
+    let worker_count = env::var("BLOCKS_WORKER_COUNT")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(3);
+
+    let mut jobs = JobSystem::new(
+        worker_count,
+        Arc::clone(&terrain_generator),
+        Arc::clone(&structure_generator),
+    );
+
+    let mut occlusion_culler = OcclusionCuller::new();
+
+    let mut fluid_simulation = FluidSimulation::new();
+
+    let mut tick_scheduler = TickScheduler::new(seed);
+
     let (file_watcher_tx, file_watcher_rx) = mpsc::channel();
     let mut file_watcher = notify::watcher(file_watcher_tx, Duration::from_millis(100)).unwrap();
     file_watcher
@@ -113,9 +197,11 @@ fn main() {
         .unwrap();
 
     let mut chunk_renderer;
+    let mut selection_renderer;
     let text_renderer;
     unsafe {
-        chunk_renderer = ChunkRenderer::new(&assets);
+        chunk_renderer = ChunkRenderer::new(&assets, &block_registry);
+        selection_renderer = SelectionRenderer::new(&assets);
         text_renderer = TextRenderer::new(&assets);
     }
 
@@ -132,6 +218,9 @@ fn main() {
     let mut input_right = glutin::ElementState::Released;
     let mut input_up = glutin::ElementState::Released;
     let mut input_down = glutin::ElementState::Released;
+    let mut input_break_block = false;
+    let mut input_place_block = false;
+    let mut selected_block = Block::new(1, 0);
 
     const DESIRED_UPS: f64 = 153.0;
     const DESIRED_FPS: f64 = 60.0;
@@ -160,6 +249,10 @@ fn main() {
     }
 
     let mut render_mode = RenderMode::Color;
+    let mut mesh_options = MeshOptions {
+        mode: MeshMode::Greedy,
+        ambient_occlusion: true,
+    };
 
     let mut r = 0.9;
     let mut g = 0.8;
@@ -174,7 +267,7 @@ fn main() {
     let mut camera = camera::Camera {
         position: Vector3 {
             x: 4.0,
-            y: 2.0,
+            y: 28.0,
             z: 10.0,
         },
         yaw: Rad::from(Deg(60.0)),
@@ -185,6 +278,11 @@ fn main() {
         zoom_velocity: 0.3,
     };
 
+    // The camera flies through blocks until walking is toggled on, after
+    // which it follows the eyes of the player.
+    let mut flying = true;
+    let mut player = Player::from_eye_position(camera.position);
+
     let mut mouse_pos = cgmath::Vector2::<f32>::zero();
 
     let mut console = console::Console::new();
@@ -347,6 +445,77 @@ fn main() {
                                             render_mode = render_mode.next();
                                         }
                                     }
+                                    Some(key @ VirtualKeyCode::Key1)
+                                    | Some(key @ VirtualKeyCode::Key2)
+                                    | Some(key @ VirtualKeyCode::Key3)
+                                    | Some(key @ VirtualKeyCode::Key4)
+                                    | Some(key @ VirtualKeyCode::Key5)
+                                    | Some(key @ VirtualKeyCode::Key6)
+                                    | Some(key @ VirtualKeyCode::Key7)
+                                    | Some(key @ VirtualKeyCode::Key8)
+                                    | Some(key @ VirtualKeyCode::Key9)
+                                    | Some(key @ VirtualKeyCode::Key0) => {
+                                        if input.state == ElementState::Pressed
+                                            && window_has_focus
+                                            && !console_has_focus
+                                        {
+                                            // Select the n-th block type, Key0
+                                            // comes after Key9 and selects the
+                                            // tenth.
+                                            let id =
+                                                key as usize - VirtualKeyCode::Key1 as usize + 1;
+                                            if id < block_registry.len() {
+                                                selected_block = Block::new(id, 0);
+                                            }
+                                        }
+                                    }
+                                    Some(key @ VirtualKeyCode::LBracket)
+                                    | Some(key @ VirtualKeyCode::RBracket) => {
+                                        if input.state == ElementState::Pressed
+                                            && window_has_focus
+                                            && !console_has_focus
+                                        {
+                                            // Cycle through all block types
+                                            // except void.
+                                            let count = block_registry.len() - 1;
+                                            let step = match key {
+                                                VirtualKeyCode::LBracket => count - 1,
+                                                _ => 1,
+                                            };
+                                            let id = (selected_block.id() - 1 + step) % count + 1;
+                                            selected_block = Block::new(id, 0);
+                                        }
+                                    }
+                                    Some(VirtualKeyCode::M) => {
+                                        if input.state == ElementState::Pressed
+                                            && window_has_focus
+                                            && !console_has_focus
+                                        {
+                                            mesh_options.mode = mesh_options.mode.next();
+                                            world.mark_all_chunks_dirty();
+                                        }
+                                    }
+                                    Some(VirtualKeyCode::O) => {
+                                        if input.state == ElementState::Pressed
+                                            && window_has_focus
+                                            && !console_has_focus
+                                        {
+                                            mesh_options.ambient_occlusion =
+                                                !mesh_options.ambient_occlusion;
+                                            world.mark_all_chunks_dirty();
+                                        }
+                                    }
+                                    Some(VirtualKeyCode::F) => {
+                                        if input.state == ElementState::Pressed
+                                            && window_has_focus
+                                            && !console_has_focus
+                                        {
+                                            flying = !flying;
+                                            if !flying {
+                                                player = Player::from_eye_position(camera.position);
+                                            }
+                                        }
+                                    }
                                     Some(VirtualKeyCode::Slash) | Some(VirtualKeyCode::Grave) => {
                                         if input.state == ElementState::Pressed
                                             && window_has_focus
@@ -388,6 +557,19 @@ fn main() {
                             WindowEvent::Focused(state) => {
                                 window_has_focus = state;
                             }
+                            WindowEvent::MouseInput { state, button, .. } => {
+                                use glutin::MouseButton;
+                                if state == ElementState::Pressed
+                                    && window_has_focus
+                                    && !console_has_focus
+                                {
+                                    match button {
+                                        MouseButton::Left => input_break_block = true,
+                                        MouseButton::Right => input_place_block = true,
+                                        _ => (),
+                                    }
+                                }
+                            }
                             WindowEvent::CursorMoved { position, .. } => {
                                 let position = position.to_physical(dpi_factor);
                                 mouse_pos.x = position.x as f32;
@@ -434,36 +616,66 @@ fn main() {
 
             use glutin::ElementState;
 
+            let input_direction = Vector3 {
+                x: match input_left {
+                    ElementState::Pressed => -1.0,
+                    ElementState::Released => 0.0,
+                } + match input_right {
+                    ElementState::Pressed => 1.0,
+                    ElementState::Released => 0.0,
+                },
+                y: match input_up {
+                    ElementState::Pressed => 1.0,
+                    ElementState::Released => 0.0,
+                } + match input_down {
+                    ElementState::Pressed => -1.0,
+                    ElementState::Released => 0.0,
+                },
+                z: match input_forward {
+                    ElementState::Pressed => -1.0,
+                    ElementState::Released => 0.0,
+                } + match input_backward {
+                    ElementState::Pressed => 1.0,
+                    ElementState::Released => 0.0,
+                },
+            };
+
             camera.update(&camera::CameraUpdate {
                 delta_time: 1.0 / DESIRED_UPS as f32,
-                delta_position: Vector3 {
-                    x: match input_left {
-                        ElementState::Pressed => -1.0,
-                        ElementState::Released => 0.0,
-                    } + match input_right {
-                        ElementState::Pressed => 1.0,
-                        ElementState::Released => 0.0,
-                    },
-                    y: match input_up {
-                        ElementState::Pressed => 1.0,
-                        ElementState::Released => 0.0,
-                    } + match input_down {
-                        ElementState::Pressed => -1.0,
-                        ElementState::Released => 0.0,
-                    },
-                    z: match input_forward {
-                        ElementState::Pressed => -1.0,
-                        ElementState::Released => 0.0,
-                    } + match input_backward {
-                        ElementState::Pressed => 1.0,
-                        ElementState::Released => 0.0,
-                    },
+                delta_position: if flying {
+                    input_direction
+                } else {
+                    Vector3::zero()
                 },
                 delta_yaw: Rad(mouse_dx as f32),
                 delta_pitch: Rad(mouse_dy as f32),
                 delta_scroll: mouse_dscroll as f32,
             });
 
+            let delta_time = 1.0 / DESIRED_UPS as f32;
+
+            if !flying {
+                // Walk in the direction the camera is facing. Blocks in
+                // chunks that are not loaded are solid so the player does
+                // not fall out of the world.
+                let direction = Quaternion::from_axis_angle(Vector3::unit_y(), -camera.yaw)
+                    * Vector3::new(input_direction.x, 0.0, input_direction.z);
+                let jump = input_up == ElementState::Pressed;
+                player.update(delta_time, direction, jump, |p| {
+                    world
+                        .chunk_at(split_block_position(p).0)
+                        .map_or(1.0, |_| block_registry.solid_height(world.block_at(p)))
+                });
+                camera.position = player.eye_position();
+            }
+
+            fluid_simulation.step(&mut world, &block_registry);
+            tick_scheduler.update(&mut world, &block_registry);
+            for position in falling::update(&mut world, &block_registry, delta_time) {
+                tick_scheduler.block_changed(position);
+                fluid_simulation.wake(position);
+            }
+
             if input_forward == ElementState::Pressed {
                 r += 1.0 / DESIRED_UPS as f32;
                 if r > 1.0 {
@@ -564,6 +776,7 @@ fn main() {
         {
             let mut chunk_renderer_changes = ChunkRendererChanges::new();
             let mut post_renderer_changes = PostRendererChanges::new();
+            let mut selection_renderer_changes = SelectionRendererChanges::new();
             let mut text_renderer_changes = TextRendererChanges::new();
 
             loop {
@@ -585,17 +798,48 @@ fn main() {
                                 if &path == &assets.text_renderer_frag {
                                     text_renderer_changes.frag = true;
                                 }
+                                if &path == &assets.selection_renderer_vert {
+                                    selection_renderer_changes.vert = true;
+                                }
+                                if &path == &assets.selection_renderer_frag {
+                                    selection_renderer_changes.frag = true;
+                                }
                                 if &path == &assets.post_renderer_vert {
                                     post_renderer_changes.vert = true;
                                 }
                                 if &path == &assets.post_renderer_frag {
                                     post_renderer_changes.frag = true;
                                 }
-                                if &path == &assets.dirt_xyz_png {
-                                    chunk_renderer_changes.dirt = true;
+                                if &path == &assets.blocks_txt {
+                                    // Blocks in the world and the generators refer to
+                                    // block types by ID, so those have to stay the same.
+                                    let registry = load_block_registry(&assets.blocks_txt)
+                                        .and_then(|registry| {
+                                            if registry.keeps_ids_of(&block_registry) {
+                                                Ok(registry)
+                                            } else {
+                                                Err(format!(
+                                                    "Ignoring changes to {}: block types can \
+                                                     only be added after the existing ones.",
+                                                    path.display()
+                                                ))
+                                            }
+                                        });
+                                    match registry {
+                                        Ok(registry) => {
+                                            block_registry = Arc::new(registry);
+                                            chunk_renderer_changes.textures = true;
+                                            light::relight_all(&mut world, &block_registry);
+                                        }
+                                        Err(err) => eprintln!("{}", err),
+                                    }
                                 }
-                                if &path == &assets.stone_xyz_png {
-                                    chunk_renderer_changes.stone = true;
+                                if block_registry
+                                    .texture_names()
+                                    .iter()
+                                    .any(|name| path == assets.block_texture_path(name))
+                                {
+                                    chunk_renderer_changes.textures = true;
                                 }
                                 if &path == &assets.font_padded_sdf_png {
                                     text_renderer_changes.font_padded_sdf_png = true;
@@ -619,21 +863,12 @@ fn main() {
             }
 
             unsafe {
-                chunk_renderer.update(&assets, chunk_renderer_changes);
+                chunk_renderer.update(&assets, &block_registry, chunk_renderer_changes);
                 post_renderer.update(&assets, post_renderer_changes);
+                selection_renderer.update(&assets, selection_renderer_changes);
             }
         }
 
-        unsafe {
-            glw::bind_framebuffer(glw::FRAMEBUFFER, &framebuffer_name);
-
-            gl::ClearColor(r, g, b, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::Enable(gl::DEPTH_TEST);
-            gl::Enable(gl::CULL_FACE);
-            // gl::Enable(gl::MULTISAMPLE);
-        }
-
         // Render scene.
         let pos_from_wld_to_cam_space = camera.pos_from_wld_to_cam_space();
 
@@ -661,9 +896,167 @@ fn main() {
         });
 
         let pos_from_wld_to_clp_space = pos_from_cam_to_clp_space * pos_from_wld_to_cam_space;
+        let frustrum_planes = FrustrumPlanes::from_matrix(&pos_from_wld_to_clp_space);
+        let chunk_in_view = |p: Vector3<i32>| {
+            let min = p.cast::<f32>().unwrap() * CHUNK_SIDE_BLOCKS as f32;
+            let max = min + Vector3::from_value(CHUNK_SIDE_BLOCKS as f32);
+            frustrum_planes.intersects_aabb(min, max)
+        };
+
+        // Find the block under the mouse cursor.
+        let find_target = |world: &World| {
+            let ray_direction = camera.orientation()
+                * frustrum.ray_direction(Vector2 {
+                    x: mouse_pos.x / viewport.width() as f32,
+                    y: 1.0 - mouse_pos.y / viewport.height() as f32,
+                });
+            raycast::raycast_world(world, camera.position, ray_direction, frustrum.z1)
+        };
+
+        let mut target = find_target(&world);
+
+        // Break or place blocks.
+        if input_break_block || input_place_block {
+            if let Some(hit) = target {
+                if input_break_block {
+                    world.set_block(hit.position, Block::VOID);
+                    fluid_simulation.wake(hit.position);
+                    tick_scheduler.block_changed(hit.position);
+                } else {
+                    let position = hit.adjacent_position();
+                    let camera_position = Vector3 {
+                        x: camera.position.x.floor() as i32,
+                        y: camera.position.y.floor() as i32,
+                        z: camera.position.z.floor() as i32,
+                    };
+                    let is_occupied = if flying {
+                        position == camera_position
+                    } else {
+                        Aabb::block(position).intersects(&player.aabb())
+                    };
+                    if !is_occupied {
+                        world.set_block(position, block_registry.placed(selected_block, hit.face));
+                        fluid_simulation.wake(position);
+                        tick_scheduler.block_changed(position);
+                    }
+                }
+                target = find_target(&world);
+            }
+            input_break_block = false;
+            input_place_block = false;
+        }
+
+        let camera_chunk = split_block_position(camera.position.map(|c| c.floor() as i32)).0;
+
+        // Load chunks around the camera and unload the ones far away.
+        {
+            let update = chunk_streamer.update(&world, camera_chunk, chunk_in_view);
+
+            for position_indices in update.unload {
+                jobs.cancel_all(position_indices);
+                if let Some(chunk) = world.remove_chunk(position_indices) {
+                    if world.take_unsaved_chunk(position_indices) {
+                        if let Err(err) = region_store.save(&chunk, &block_registry) {
+                            eprintln!("Failed to save chunk {:?}: {}", position_indices, err);
+                        }
+                    }
+                }
+            }
+
+            // Chunks are read from disk or generated on the worker threads.
+            for position_indices in update.load {
+                if !jobs.is_busy(JobKind::Load, position_indices) {
+                    jobs.load(
+                        position_indices,
+                        Arc::clone(&region_store),
+                        Arc::clone(&block_registry),
+                    );
+                }
+            }
+        }
+
+        // Take the results of the worker threads.
+        for result in jobs.finished() {
+            match result {
+                JobResult::Loaded(chunk) => {
+                    fluid_simulation.wake_chunk(&chunk, &block_registry);
+                    world.insert_chunk(chunk);
+                }
+                JobResult::Generated(chunk) => {
+                    let position_indices = chunk.position_indices;
+                    fluid_simulation.wake_chunk(&chunk, &block_registry);
+                    world.insert_chunk(chunk);
+                    world.mark_chunk_unsaved(position_indices);
+                }
+                JobResult::LoadFailed(position_indices, err) => {
+                    // Leave the chunk out rather than generating it, which
+                    // would overwrite the saved chunk once it is unloaded. It
+                    // is tried again when it comes back into range.
+                    eprintln!("Failed to load chunk {:?}: {}", position_indices, err);
+                }
+                JobResult::Meshed(position_indices, mesh, connectivity) => {
+                    occlusion_culler.set_chunk(position_indices, connectivity);
+                    unsafe {
+                        chunk_renderer.update_chunk(position_indices, &mesh);
+                    }
+                }
+            }
+        }
+
+        // Light changes mark the chunks they affect as dirty.
+        light::update(&mut world, &block_registry);
+
+        // Rebuild the meshes of chunks that changed in the background.
+        for position_indices in world.take_dirty_chunks() {
+            if world.chunk_at(position_indices).is_some() {
+                let padded = PaddedChunk::new(&world, position_indices);
+                jobs.mesh(position_indices, padded, Arc::clone(&block_registry), mesh_options);
+            } else {
+                jobs.cancel(JobKind::Mesh, position_indices);
+                occlusion_culler.remove_chunk(position_indices);
+                unsafe {
+                    chunk_renderer.remove_chunk(position_indices);
+                }
+            }
+        }
 
         unsafe {
-            chunk_renderer.render(&pos_from_wld_to_clp_space, &chunk);
+            glw::bind_framebuffer(glw::FRAMEBUFFER, &framebuffer_name);
+
+            gl::ClearColor(r, g, b, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Enable(gl::CULL_FACE);
+            // gl::Enable(gl::MULTISAMPLE);
+        }
+
+        let visible_chunks = occlusion_culler.visible_chunks(camera_chunk, chunk_in_view);
+
+        let mut entity_vertices = Vec::new();
+        for falling_block in world.falling_blocks() {
+            let light = world
+                .light_at(falling_block.position.map(|c| c.floor() as i32))
+                .unwrap_or(Light::DARK);
+            mesher::mesh_block(
+                &mut entity_vertices,
+                &block_registry,
+                falling_block.block,
+                falling_block.position,
+                light,
+            );
+        }
+
+        let chunk_render_stats;
+        unsafe {
+            chunk_renderer.update_entities(&entity_vertices);
+            chunk_render_stats =
+                chunk_renderer.render(&pos_from_wld_to_clp_space, camera.position, |p| {
+                    visible_chunks.contains(&p)
+                });
+
+            if let Some(ref hit) = target {
+                selection_renderer.render(&pos_from_wld_to_clp_space, hit.position);
+            }
         }
 
         // Render ui
@@ -685,14 +1078,62 @@ fn main() {
             });
 
             {
-                let s = format!(
-                    "{} {}, {:.0} FPS, {:.0} UPS",
+                let streaming_stats = chunk_streamer.stats(&world);
+                let mut s = format!(
+                    "{} {}, {:.0} FPS, {:.0} UPS, {} chunks, {} pending, {} jobs",
                     env!("CARGO_PKG_NAME"),
                     env!("GIT_HASH"),
                     fps,
-                    ups
+                    ups,
+                    streaming_stats.loaded,
+                    streaming_stats.pending,
+                    jobs.job_count()
                 );
 
+                if render_mode == RenderMode::Debug {
+                    s.push_str(&format!(
+                        "\n{:?} mesh{}, {} triangles, placing {}",
+                        mesh_options.mode,
+                        if mesh_options.ambient_occlusion { " with AO" } else { "" },
+                        chunk_renderer.triangle_count(),
+                        block_registry.get(selected_block).name
+                    ));
+
+                    s.push_str(&format!(
+                        "\n{} chunks drawn, {} culled, {} occluded",
+                        chunk_render_stats.drawn,
+                        chunk_render_stats.culled,
+                        chunk_render_stats.occluded
+                    ));
+
+                    s.push_str(&format!(
+                        "\n{} fluid blocks active, {} ticks scheduled",
+                        fluid_simulation.active_count(),
+                        tick_scheduler.scheduled_count()
+                    ));
+
+                    s.push_str(if flying { "\nflying" } else { "\nwalking" });
+
+                    let camera_position = camera.position.map(|c| c.floor() as i32);
+                    if let Some(biome) = world.biome_at(camera_position) {
+                        s.push_str(&format!("\nbiome {:?}", biome));
+                    }
+                    if let Some(light) = world.light_at(camera_position) {
+                        s.push_str(&format!(
+                            "\nsky light {}, block light {}",
+                            light.sky(),
+                            light.block()
+                        ));
+                    }
+
+                    if let Some(ref hit) = target {
+                        s.push_str(&format!(
+                            "\ntarget {:?} {:?} at {:.1}",
+                            hit.position, hit.face, hit.distance
+                        ));
+                    }
+                }
+
                 text_renderer.render(
                     &pos_from_wld_to_clp_space,
                     &s,
@@ -729,8 +1170,17 @@ fn main() {
             + time::Duration::from_nanos((1000_000_000f64 / DESIRED_FPS) as u64);
     }
 
+    for position_indices in world.take_unsaved_chunks() {
+        if let Some(chunk) = world.chunk_at(position_indices) {
+            if let Err(err) = region_store.save(chunk, &block_registry) {
+                eprintln!("Failed to save chunk {:?}: {}", position_indices, err);
+            }
+        }
+    }
+
     unsafe {
         chunk_renderer.delete();
+        selection_renderer.delete();
         post_renderer.delete();
         text_renderer.delete();
 
@@ -0,0 +1,1064 @@
+use block::Block;
+use block_registry::BlockRegistry;
+use block_registry::RenderLayer;
+use block_registry::MAX_LIGHT;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_SIDE_BLOCKS;
+use cube::Face;
+use fluid::fluid_height;
+use light::Light;
+use world::World;
+
+pub const PADDED_SIDE_BLOCKS: usize = CHUNK_SIDE_BLOCKS + 2;
+pub const PADDED_TOTAL_BLOCKS: usize = PADDED_SIDE_BLOCKS * PADDED_SIDE_BLOCKS * PADDED_SIDE_BLOCKS;
+
+/// A copy of the blocks in a chunk surrounded by a one block thick border
+/// taken from the neighbouring chunks. This is everything the mesher
+/// needs to decide which faces are visible and how they are lit.
+pub struct PaddedChunk {
+    blocks: Vec<Block>,
+    light: Vec<Light>,
+}
+
+/// Light assumed outside loaded chunks.
+const OPEN_SKY: Light = Light(MAX_LIGHT << 4);
+
+impl PaddedChunk {
+    #[inline]
+    fn block_index(x: i32, y: i32, z: i32) -> usize {
+        debug_assert!(x >= -1 && x <= CHUNK_SIDE_BLOCKS as i32);
+        debug_assert!(y >= -1 && y <= CHUNK_SIDE_BLOCKS as i32);
+        debug_assert!(z >= -1 && z <= CHUNK_SIDE_BLOCKS as i32);
+        (((z + 1) as usize * PADDED_SIDE_BLOCKS + (y + 1) as usize) * PADDED_SIDE_BLOCKS)
+            + (x + 1) as usize
+    }
+
+    /// Copies a chunk and its border from the world. Blocks in chunks
+    /// that are not loaded are treated as void and open to the sky.
+    pub fn new(world: &World, position_indices: Vector3<i32>) -> Self {
+        let offset = position_indices * CHUNK_SIDE_BLOCKS as i32;
+        let side = CHUNK_SIDE_BLOCKS as i32;
+        let chunk = world.chunk_at(position_indices);
+        let mut blocks = Vec::with_capacity(PADDED_TOTAL_BLOCKS);
+        let mut light = Vec::with_capacity(PADDED_TOTAL_BLOCKS);
+        for z in -1..=side {
+            for y in -1..=side {
+                for x in -1..=side {
+                    let inside = x >= 0 && x < side && y >= 0 && y < side && z >= 0 && z < side;
+                    match chunk {
+                        Some(chunk) if inside => {
+                            let (x, y, z) = (x as usize, y as usize, z as usize);
+                            blocks.push(*chunk.block_at(x, y, z));
+                            light.push(chunk.light_at(x, y, z));
+                        }
+                        _ => {
+                            let p = offset + Vector3 { x, y, z };
+                            blocks.push(world.block_at(p));
+                            light.push(world.light_at(p).unwrap_or(OPEN_SKY));
+                        }
+                    }
+                }
+            }
+        }
+        PaddedChunk { blocks, light }
+    }
+
+    /// Copies a chunk with a void border that is open to the sky.
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        let side = CHUNK_SIDE_BLOCKS as i32;
+        let mut padded = PaddedChunk {
+            blocks: vec![Block::VOID; PADDED_TOTAL_BLOCKS],
+            light: vec![OPEN_SKY; PADDED_TOTAL_BLOCKS],
+        };
+        for z in 0..side {
+            for y in 0..side {
+                for x in 0..side {
+                    let (cx, cy, cz) = (x as usize, y as usize, z as usize);
+                    *padded.block_at_mut(x, y, z) = *chunk.block_at(cx, cy, cz);
+                    *padded.light_at_mut(x, y, z) = chunk.light_at(cx, cy, cz);
+                }
+            }
+        }
+        padded
+    }
+
+    /// Coordinates range from -1 to CHUNK_SIDE_BLOCKS inclusive.
+    #[inline]
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Block {
+        self.blocks[Self::block_index(x, y, z)]
+    }
+
+    #[inline]
+    pub fn block_at_mut(&mut self, x: i32, y: i32, z: i32) -> &mut Block {
+        &mut self.blocks[Self::block_index(x, y, z)]
+    }
+
+    #[inline]
+    fn block_at_vec(&self, p: Vector3<i32>) -> Block {
+        self.block_at(p.x, p.y, p.z)
+    }
+
+    #[inline]
+    pub fn light_at(&self, x: i32, y: i32, z: i32) -> Light {
+        self.light[Self::block_index(x, y, z)]
+    }
+
+    #[inline]
+    pub fn light_at_mut(&mut self, x: i32, y: i32, z: i32) -> &mut Light {
+        &mut self.light[Self::block_index(x, y, z)]
+    }
+
+    #[inline]
+    fn light_at_vec(&self, p: Vector3<i32>) -> Light {
+        self.light_at(p.x, p.y, p.z)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MeshMode {
+    /// One quad per visible block face.
+    Culled,
+    /// Visible faces of the same block type in the same plane are merged
+    /// into larger quads.
+    Greedy,
+}
+
+impl MeshMode {
+    pub fn next(&self) -> MeshMode {
+        match *self {
+            MeshMode::Culled => MeshMode::Greedy,
+            MeshMode::Greedy => MeshMode::Culled,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MeshOptions {
+    pub mode: MeshMode,
+    /// Darken the corners of faces next to opaque blocks.
+    pub ambient_occlusion: bool,
+}
+
+/// Ambient occlusion of a corner that is not occluded at all.
+pub const MAX_AO: u8 = 3;
+
+/// Ambient occlusion of a face corner from the two blocks sharing an edge
+/// with the corner and the block sharing just the corner, all in the layer
+/// in front of the face. Two edge blocks hide the corner block so the
+/// corner is fully occluded either way.
+#[inline]
+pub fn corner_ao(side_a: bool, side_b: bool, corner: bool) -> u8 {
+    if side_a && side_b {
+        0
+    } else {
+        MAX_AO - side_a as u8 - side_b as u8 - corner as u8
+    }
+}
+
+/// Ambient occlusion of each corner of the `face` of the block at `p`, in
+/// the order of `Face::corners`.
+fn face_ao(padded: &PaddedChunk, registry: &BlockRegistry, p: Vector3<i32>, face: Face) -> [u8; 4] {
+    let front = p + face.normal();
+    let (u_axis, v_axis) = face.tex_axes();
+    let is_opaque = |p: Vector3<i32>| registry.is_opaque(padded.block_at_vec(p));
+    let mut ao = [MAX_AO; 4];
+    for (ao, corner) in ao.iter_mut().zip(face.corners().iter()) {
+        let mut du = Vector3::zero();
+        du[u_axis] = if corner.ver_pos[u_axis] == 1 { 1 } else { -1 };
+        let mut dv = Vector3::zero();
+        dv[v_axis] = if corner.ver_pos[v_axis] == 1 { 1 } else { -1 };
+        *ao = corner_ao(
+            is_opaque(front + du),
+            is_opaque(front + dv),
+            is_opaque(front + du + dv),
+        );
+    }
+    ao
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex {
+    pub ver_pos: Vector3<f32>,
+    pub tex_pos: Vector2<f32>,
+    pub tex_layer: u32,
+    /// Sky and block light from 0 to 1.
+    pub light: Vector2<f32>,
+    /// Ambient occlusion from 0 for a fully occluded corner to 1 for an
+    /// open one.
+    pub ao: f32,
+}
+
+pub const VERTICES_PER_FACE: usize = 6;
+
+/// Triangle lists in chunk space, one per render layer. The block at
+/// (x, y, z) spans [x, x + 1] along each axis.
+pub struct ChunkMesh {
+    pub vertices: Vec<MeshVertex>,
+    pub cutout_vertices: Vec<MeshVertex>,
+    pub translucent_vertices: Vec<MeshVertex>,
+}
+
+impl ChunkMesh {
+    pub fn new() -> Self {
+        ChunkMesh {
+            vertices: Vec::new(),
+            cutout_vertices: Vec::new(),
+            translucent_vertices: Vec::new(),
+        }
+    }
+
+    pub fn layer(&self, layer: RenderLayer) -> &[MeshVertex] {
+        match layer {
+            RenderLayer::Opaque => &self.vertices,
+            RenderLayer::Cutout => &self.cutout_vertices,
+            RenderLayer::Translucent => &self.translucent_vertices,
+        }
+    }
+
+    fn layer_mut(&mut self, layer: RenderLayer) -> &mut Vec<MeshVertex> {
+        match layer {
+            RenderLayer::Opaque => &mut self.vertices,
+            RenderLayer::Cutout => &mut self.cutout_vertices,
+            RenderLayer::Translucent => &mut self.translucent_vertices,
+        }
+    }
+
+    #[inline]
+    fn vertex_count(&self) -> usize {
+        self.vertices.len() + self.cutout_vertices.len() + self.translucent_vertices.len()
+    }
+
+    #[inline]
+    pub fn face_count(&self) -> usize {
+        self.vertex_count() / VERTICES_PER_FACE
+    }
+
+    #[inline]
+    pub fn triangle_count(&self) -> usize {
+        self.vertex_count() / 3
+    }
+
+    /// Emits a quad on the `face` side of the box starting at block
+    /// `origin` spanning `size` blocks along each axis. Texture
+    /// coordinates run from 0 to the size of the quad so the texture
+    /// repeats once per block. The whole quad gets the same `light`. The
+    /// quad is split into triangles along the diagonal whose corners are
+    /// the least occluded so the occlusion is interpolated symmetrically.
+    fn push_quad(
+        &mut self,
+        registry: &BlockRegistry,
+        origin: Vector3<i32>,
+        size: Vector3<i32>,
+        face: Face,
+        block: Block,
+        light: Light,
+        ao: [u8; 4],
+    ) {
+        let corners = face.corners();
+        let light = light_levels(light);
+        let (tex_layer, swap_tex_axes) = registry.face_texture(block, face);
+        let (u_axis, v_axis) = face.tex_axes();
+        let tex_size = Vector2 {
+            x: size[u_axis],
+            y: size[v_axis],
+        };
+        let vertex = |i: usize| {
+            let c = &corners[i];
+            let p = c.ver_pos.cast::<i32>().unwrap();
+            let t = c.tex_pos.cast::<i32>().unwrap().mul_element_wise(tex_size);
+            let t = if swap_tex_axes {
+                Vector2::new(t.y, t.x)
+            } else {
+                t
+            };
+            MeshVertex {
+                ver_pos: (origin + p.mul_element_wise(size)).cast::<f32>().unwrap(),
+                tex_pos: t.cast::<f32>().unwrap(),
+                tex_layer,
+                light,
+                ao: ao[i] as f32 / MAX_AO as f32,
+            }
+        };
+        let v = [vertex(0), vertex(1), vertex(2), vertex(3)];
+        let vertices = self.layer_mut(registry.render_layer(block));
+        if ao[0] + ao[2] < ao[1] + ao[3] {
+            vertices.extend_from_slice(&[v[1], v[2], v[3], v[3], v[0], v[1]]);
+        } else {
+            vertices.extend_from_slice(&[v[0], v[1], v[2], v[2], v[3], v[0]]);
+        }
+    }
+}
+
+#[inline]
+fn light_levels(light: Light) -> Vector2<f32> {
+    Vector2::new(light.sky(), light.block())
+        .cast::<f32>()
+        .unwrap()
+        / MAX_LIGHT as f32
+}
+
+/// Faces between two blocks of the same translucent type are hidden, so
+/// a body of water or glass only shows its outside. Fluids and blocks
+/// lower than a full block are meshed separately by `mesh_fluids` and
+/// `mesh_low_blocks`.
+#[inline]
+fn is_face_visible(registry: &BlockRegistry, block: Block, neighbour: Block) -> bool {
+    block != Block::VOID
+        && registry.fluid(block).is_none()
+        && registry.height(block) >= 1.0
+        && !registry.is_opaque(neighbour)
+        && !(neighbour == block && registry.render_layer(block) == RenderLayer::Translucent)
+}
+
+/// Sorts the quads of a triangle list so the ones furthest from `eye`, in
+/// chunk space, come first. Drawing them in that order blends each quad
+/// over the ones behind it.
+pub fn sort_back_to_front(vertices: &mut Vec<MeshVertex>, eye: Vector3<f32>) {
+    let mut quads: Vec<(f32, &[MeshVertex])> = vertices
+        .chunks(VERTICES_PER_FACE)
+        .map(|quad| {
+            // Both ways of splitting a quad list its corners at 0, 1, 2 and 4.
+            let center =
+                (quad[0].ver_pos + quad[1].ver_pos + quad[2].ver_pos + quad[4].ver_pos) / 4.0;
+            ((center - eye).magnitude2(), quad)
+        })
+        .collect();
+    quads.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    let sorted: Vec<MeshVertex> = quads
+        .into_iter()
+        .flat_map(|(_, quad)| quad.iter().cloned())
+        .collect();
+    *vertices = sorted;
+}
+
+/// Emits one quad for every block face that borders void.
+pub fn mesh_culled(
+    padded: &PaddedChunk,
+    registry: &BlockRegistry,
+    ambient_occlusion: bool,
+) -> ChunkMesh {
+    let mut mesh = ChunkMesh::new();
+    let side = CHUNK_SIDE_BLOCKS as i32;
+    for z in 0..side {
+        for y in 0..side {
+            for x in 0..side {
+                let p = Vector3 { x, y, z };
+                let block = padded.block_at_vec(p);
+                if block == Block::VOID {
+                    continue;
+                }
+                for &face in Face::ALL.iter() {
+                    let n = p + face.normal();
+                    if is_face_visible(registry, block, padded.block_at_vec(n)) {
+                        let light = padded.light_at_vec(n);
+                        let ao = if ambient_occlusion {
+                            face_ao(padded, registry, p, face)
+                        } else {
+                            [MAX_AO; 4]
+                        };
+                        let size = Vector3::new(1, 1, 1);
+                        mesh.push_quad(registry, p, size, face, block, light, ao);
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
+
+/// Merges visible faces of the same block type, light and ambient
+/// occlusion lying in the same plane into rectangles. Only faces with the
+/// same occlusion at every corner are merged, larger quads would
+/// interpolate it differently.
+pub fn mesh_greedy(
+    padded: &PaddedChunk,
+    registry: &BlockRegistry,
+    ambient_occlusion: bool,
+) -> ChunkMesh {
+    const SIDE: usize = CHUNK_SIDE_BLOCKS;
+    const EMPTY: (Block, Light, [u8; 4]) = (Block::VOID, Light::DARK, [MAX_AO; 4]);
+    let mut mesh = ChunkMesh::new();
+    let mut mask = [EMPTY; SIDE * SIDE];
+
+    for &face in Face::ALL.iter() {
+        let axis = face.axis();
+        let u_axis = (axis + 1) % 3;
+        let v_axis = (axis + 2) % 3;
+        let normal = face.normal();
+
+        for d in 0..SIDE {
+            // Collect the visible faces in this slice.
+            for j in 0..SIDE {
+                for i in 0..SIDE {
+                    let mut p = Vector3::zero();
+                    p[axis] = d as i32;
+                    p[u_axis] = i as i32;
+                    p[v_axis] = j as i32;
+                    let block = padded.block_at_vec(p);
+                    let n = p + normal;
+                    mask[j * SIDE + i] = if is_face_visible(registry, block, padded.block_at_vec(n))
+                    {
+                        let ao = if ambient_occlusion {
+                            face_ao(padded, registry, p, face)
+                        } else {
+                            [MAX_AO; 4]
+                        };
+                        (block, padded.light_at_vec(n), ao)
+                    } else {
+                        EMPTY
+                    };
+                }
+            }
+
+            // Grow rectangles first along u, then along v.
+            for j in 0..SIDE {
+                let mut i = 0;
+                while i < SIDE {
+                    let face_key = mask[j * SIDE + i];
+                    if face_key.0 == Block::VOID {
+                        i += 1;
+                        continue;
+                    }
+
+                    let (block, light, ao) = face_key;
+                    let mergeable = ao.iter().all(|&a| a == ao[0]);
+
+                    let mut w = 1;
+                    while mergeable && i + w < SIDE && mask[j * SIDE + i + w] == face_key {
+                        w += 1;
+                    }
+
+                    let mut h = 1;
+                    'grow: while mergeable && j + h < SIDE {
+                        for k in 0..w {
+                            if mask[(j + h) * SIDE + i + k] != face_key {
+                                break 'grow;
+                            }
+                        }
+                        h += 1;
+                    }
+
+                    for l in 0..h {
+                        for k in 0..w {
+                            mask[(j + l) * SIDE + i + k] = EMPTY;
+                        }
+                    }
+
+                    let mut origin = Vector3::zero();
+                    origin[axis] = d as i32;
+                    origin[u_axis] = i as i32;
+                    origin[v_axis] = j as i32;
+                    let mut size = Vector3::new(1, 1, 1);
+                    size[u_axis] = w as i32;
+                    size[v_axis] = h as i32;
+                    mesh.push_quad(registry, origin, size, face, block, light, ao);
+
+                    i += w;
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Height of the fluid surface at the block corner (x, z), the average
+/// over the fluid blocks of type `id` at height `y` sharing that corner.
+/// The surface reaches the top of the block if one of them has the same
+/// fluid above it.
+fn fluid_corner_height(padded: &PaddedChunk, id: usize, spread: u8, x: i32, y: i32, z: i32) -> f32 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for dz in -1..1 {
+        for dx in -1..1 {
+            let block = padded.block_at(x + dx, y, z + dz);
+            if block.id() != id {
+                continue;
+            }
+            if padded.block_at(x + dx, y + 1, z + dz).id() == id {
+                return 1.0;
+            }
+            sum += fluid_height(block, spread);
+            count += 1;
+        }
+    }
+    sum / count as f32
+}
+
+/// Emits `face` of the block at `p` with the corners of its top at
+/// `heights` above its bottom, indexed by z and x. The top is lit by the
+/// block itself because it is below the top of the block.
+fn push_cut_face(
+    mesh: &mut ChunkMesh,
+    padded: &PaddedChunk,
+    registry: &BlockRegistry,
+    p: Vector3<i32>,
+    block: Block,
+    face: Face,
+    heights: [[f32; 2]; 2],
+) {
+    let light = light_levels(if face == Face::PosY {
+        padded.light_at_vec(p)
+    } else {
+        padded.light_at_vec(p + face.normal())
+    });
+    let (tex_layer, _) = registry.face_texture(block, face);
+    let vertex = |i: usize| {
+        let c = &face.corners()[i];
+        let mut ver_pos = c.ver_pos.cast::<f32>().unwrap();
+        if c.ver_pos.y == 1 {
+            ver_pos.y = heights[c.ver_pos.z as usize][c.ver_pos.x as usize];
+        }
+        // Sides are cut off at the top, not squashed.
+        let tex_pos = if face.axis() == 1 {
+            c.tex_pos.cast::<f32>().unwrap()
+        } else {
+            Vector2::new(c.tex_pos.x as f32, ver_pos.y)
+        };
+        MeshVertex {
+            ver_pos: p.cast::<f32>().unwrap() + ver_pos,
+            tex_pos,
+            tex_layer,
+            light,
+            ao: 1.0,
+        }
+    };
+    let v = [vertex(0), vertex(1), vertex(2), vertex(3)];
+    mesh.layer_mut(registry.render_layer(block))
+        .extend_from_slice(&[v[0], v[1], v[2], v[2], v[3], v[0]]);
+}
+
+/// Emits the faces of fluid blocks that do not border the same fluid.
+/// Their tops slope down towards neighbours with lower levels and their
+/// sides reach up to the surface.
+pub fn mesh_fluids(padded: &PaddedChunk, registry: &BlockRegistry, mesh: &mut ChunkMesh) {
+    let side = CHUNK_SIDE_BLOCKS as i32;
+    for z in 0..side {
+        for y in 0..side {
+            for x in 0..side {
+                let p = Vector3 { x, y, z };
+                let block = padded.block_at_vec(p);
+                let spread = match registry.fluid(block) {
+                    Some(properties) => properties.spread,
+                    None => continue,
+                };
+                let id = block.id();
+                let mut heights = [[0.0; 2]; 2];
+                for cz in 0..2 {
+                    for cx in 0..2 {
+                        heights[cz][cx] = fluid_corner_height(
+                            padded,
+                            id,
+                            spread,
+                            x + cx as i32,
+                            y,
+                            z + cz as i32,
+                        );
+                    }
+                }
+
+                for &face in Face::ALL.iter() {
+                    let neighbour = padded.block_at_vec(p + face.normal());
+                    // The surface is below the top of the block, so it shows
+                    // even under opaque blocks.
+                    if neighbour.id() == id || face != Face::PosY && registry.is_opaque(neighbour) {
+                        continue;
+                    }
+                    push_cut_face(mesh, padded, registry, p, block, face, heights);
+                }
+            }
+        }
+    }
+}
+
+/// Emits the faces of blocks lower than a full block, except sides facing
+/// opaque blocks or the same type of block. Their tops are always shown.
+pub fn mesh_low_blocks(padded: &PaddedChunk, registry: &BlockRegistry, mesh: &mut ChunkMesh) {
+    let side = CHUNK_SIDE_BLOCKS as i32;
+    for z in 0..side {
+        for y in 0..side {
+            for x in 0..side {
+                let p = Vector3 { x, y, z };
+                let block = padded.block_at_vec(p);
+                let height = registry.height(block);
+                if block == Block::VOID || registry.fluid(block).is_some() || height >= 1.0 {
+                    continue;
+                }
+                for &face in Face::ALL.iter() {
+                    let neighbour = padded.block_at_vec(p + face.normal());
+                    let hidden = registry.is_opaque(neighbour)
+                        || face.axis() != 1 && neighbour.id() == block.id();
+                    if face != Face::PosY && hidden {
+                        continue;
+                    }
+                    push_cut_face(mesh, padded, registry, p, block, face, [[height; 2]; 2]);
+                }
+            }
+        }
+    }
+}
+
+/// Emits all faces of a block that is not part of a chunk, like a falling
+/// block, with its minimum corner at `position`.
+pub fn mesh_block(
+    vertices: &mut Vec<MeshVertex>,
+    registry: &BlockRegistry,
+    block: Block,
+    position: Vector3<f32>,
+    light: Light,
+) {
+    let light = light_levels(light);
+    for &face in Face::ALL.iter() {
+        let (tex_layer, swap_tex_axes) = registry.face_texture(block, face);
+        let vertex = |i: usize| {
+            let c = &face.corners()[i];
+            let t = c.tex_pos.cast::<f32>().unwrap();
+            MeshVertex {
+                ver_pos: position + c.ver_pos.cast::<f32>().unwrap(),
+                tex_pos: if swap_tex_axes {
+                    Vector2::new(t.y, t.x)
+                } else {
+                    t
+                },
+                tex_layer,
+                light,
+                ao: 1.0,
+            }
+        };
+        let v = [vertex(0), vertex(1), vertex(2), vertex(3)];
+        vertices.extend_from_slice(&[v[0], v[1], v[2], v[2], v[3], v[0]]);
+    }
+}
+
+pub fn mesh(padded: &PaddedChunk, registry: &BlockRegistry, options: MeshOptions) -> ChunkMesh {
+    let mut mesh = match options.mode {
+        MeshMode::Culled => mesh_culled(padded, registry, options.ambient_occlusion),
+        MeshMode::Greedy => mesh_greedy(padded, registry, options.ambient_occlusion),
+    };
+    mesh_fluids(padded, registry, &mut mesh);
+    mesh_low_blocks(padded, registry, &mut mesh);
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::CHUNK_TOTAL_BLOCKS;
+
+    const STONE: Block = Block(1);
+    const DIRT: Block = Block(2);
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::parse(
+            "[stone]\ntexture = stone_xyz.png\n[dirt]\ntexture = dirt_xyz.png",
+            |_| true,
+        )
+        .unwrap()
+    }
+
+    fn chunk_with(blocks: &[(usize, usize, usize)]) -> Chunk {
+        let mut chunk = Chunk::new(Vector3::zero());
+        for &(x, y, z) in blocks {
+            *chunk.block_at_mut(x, y, z) = STONE;
+        }
+        chunk
+    }
+
+    #[test]
+    fn empty_chunk_has_no_faces() {
+        let chunk = chunk_with(&[]);
+        assert_eq!(
+            mesh_culled(&PaddedChunk::from_chunk(&chunk), &registry(), false).face_count(),
+            0
+        );
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let chunk = chunk_with(&[(3, 4, 5)]);
+        assert_eq!(
+            mesh_culled(&PaddedChunk::from_chunk(&chunk), &registry(), false).face_count(),
+            6
+        );
+    }
+
+    #[test]
+    fn adjacent_blocks_hide_shared_faces() {
+        let chunk = chunk_with(&[(3, 4, 5), (4, 4, 5)]);
+        assert_eq!(
+            mesh_culled(&PaddedChunk::from_chunk(&chunk), &registry(), false).face_count(),
+            10
+        );
+    }
+
+    #[test]
+    fn full_chunk_only_has_outer_faces() {
+        let mut chunk = Chunk::new(Vector3::zero());
+        chunk.blocks.fill(DIRT);
+        let n = CHUNK_SIDE_BLOCKS * CHUNK_SIDE_BLOCKS * 6;
+        assert_eq!(
+            mesh_culled(&PaddedChunk::from_chunk(&chunk), &registry(), false).face_count(),
+            n
+        );
+    }
+
+    fn area(mesh: &ChunkMesh) -> f32 {
+        mesh.vertices
+            .chunks(3)
+            .map(|t| {
+                (t[1].ver_pos - t[0].ver_pos)
+                    .cross(t[2].ver_pos - t[0].ver_pos)
+                    .magnitude()
+                    / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn greedy_merges_full_chunk_into_six_quads() {
+        let mut chunk = Chunk::new(Vector3::zero());
+        chunk.blocks.fill(STONE);
+        let mesh = mesh_greedy(&PaddedChunk::from_chunk(&chunk), &registry(), false);
+        assert_eq!(mesh.face_count(), 6);
+        for v in mesh.vertices.iter() {
+            assert!(v.tex_pos.x == 0.0 || v.tex_pos.x == CHUNK_SIDE_BLOCKS as f32);
+            assert!(v.tex_pos.y == 0.0 || v.tex_pos.y == CHUNK_SIDE_BLOCKS as f32);
+        }
+    }
+
+    #[test]
+    fn greedy_does_not_merge_different_blocks() {
+        let mut chunk = Chunk::new(Vector3::zero());
+        for z in 0..CHUNK_SIDE_BLOCKS {
+            for x in 0..CHUNK_SIDE_BLOCKS {
+                *chunk.block_at_mut(x, 0, z) = if x < 8 { STONE } else { DIRT };
+            }
+        }
+        // Top and bottom: 2 each, -X and +X: 1 each, -Z and +Z: 2 each.
+        assert_eq!(
+            mesh_greedy(&PaddedChunk::from_chunk(&chunk), &registry(), false).face_count(),
+            10
+        );
+    }
+
+    #[test]
+    fn greedy_covers_same_area_as_culled() {
+        let mut chunk = Chunk::new(Vector3::zero());
+        let mut seed: u32 = 12345;
+        for i in 0..CHUNK_TOTAL_BLOCKS {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let block = match (seed >> 16) % 3 {
+                0 => Block::VOID,
+                1 => STONE,
+                _ => DIRT,
+            };
+            chunk.blocks.set(i, block);
+        }
+        let padded = PaddedChunk::from_chunk(&chunk);
+        for &ambient_occlusion in [false, true].iter() {
+            let culled = mesh_culled(&padded, &registry(), ambient_occlusion);
+            let greedy = mesh_greedy(&padded, &registry(), ambient_occlusion);
+            assert!(greedy.face_count() < culled.face_count());
+            assert_eq!(area(&greedy), culled.face_count() as f32);
+        }
+    }
+
+    #[test]
+    fn corner_ao_counts_occluding_neighbours() {
+        assert_eq!(corner_ao(false, false, false), 3);
+        assert_eq!(corner_ao(true, false, false), 2);
+        assert_eq!(corner_ao(false, true, false), 2);
+        assert_eq!(corner_ao(false, false, true), 2);
+        assert_eq!(corner_ao(true, false, true), 1);
+        assert_eq!(corner_ao(false, true, true), 1);
+        // Both edges occlude the corner regardless of the corner block.
+        assert_eq!(corner_ao(true, true, false), 0);
+        assert_eq!(corner_ao(true, true, true), 0);
+    }
+
+    /// Ambient occlusion of the top face of a block at (5, 5, 5) with the
+    /// given blocks around it, keyed by corner position.
+    fn top_face_ao(blocks: &[(usize, usize, usize)]) -> Vec<((u8, u8), u8)> {
+        let mut all = vec![(5, 5, 5)];
+        all.extend_from_slice(blocks);
+        let padded = PaddedChunk::from_chunk(&chunk_with(&all));
+        let ao = face_ao(&padded, &registry(), Vector3::new(5, 5, 5), Face::PosY);
+        let mut corners: Vec<((u8, u8), u8)> = Face::PosY
+            .corners()
+            .iter()
+            .zip(ao.iter())
+            .map(|(c, &ao)| ((c.ver_pos.x, c.ver_pos.z), ao))
+            .collect();
+        corners.sort();
+        corners
+    }
+
+    #[test]
+    fn face_ao_in_edge_and_corner_configurations() {
+        // Open.
+        assert_eq!(
+            top_face_ao(&[]),
+            vec![((0, 0), 3), ((0, 1), 3), ((1, 0), 3), ((1, 1), 3)]
+        );
+        // A block diagonally above the +x +z corner.
+        assert_eq!(
+            top_face_ao(&[(6, 6, 6)]),
+            vec![((0, 0), 3), ((0, 1), 3), ((1, 0), 3), ((1, 1), 2)]
+        );
+        // A wall along the -z edge.
+        assert_eq!(
+            top_face_ao(&[(4, 6, 4), (5, 6, 4), (6, 6, 4)]),
+            vec![((0, 0), 1), ((0, 1), 3), ((1, 0), 1), ((1, 1), 3)]
+        );
+        // Walls along the -x and -z edges meeting in a corner.
+        assert_eq!(
+            top_face_ao(&[(4, 6, 5), (5, 6, 4)]),
+            vec![((0, 0), 0), ((0, 1), 2), ((1, 0), 2), ((1, 1), 3)]
+        );
+        // Blocks below the face do not occlude it.
+        assert_eq!(
+            top_face_ao(&[(4, 5, 5), (5, 5, 4), (4, 5, 4)]),
+            vec![((0, 0), 3), ((0, 1), 3), ((1, 0), 3), ((1, 1), 3)]
+        );
+    }
+
+    #[test]
+    fn flips_diagonals_towards_occluded_corners() {
+        let chunk = chunk_with(&[(5, 5, 5), (4, 6, 4)]);
+        let padded = PaddedChunk::from_chunk(&chunk);
+        let mesh = mesh_culled(&padded, &registry(), true);
+        // Skip the bottom face of the occluding block in the same plane.
+        let top: Vec<MeshVertex> = vertices_in_plane_y(&mesh, 6.0)
+            .chunks(3)
+            .filter(|t| t.iter().all(|v| v.ver_pos.x >= 5.0 && v.ver_pos.z >= 5.0))
+            .flat_map(|t| t.iter().cloned())
+            .collect();
+        assert_eq!(top.len(), VERTICES_PER_FACE);
+        // The occluded corner is only part of one of the two triangles.
+        let dark = Vector3::new(5.0, 6.0, 5.0);
+        let triangles_with_dark = top
+            .chunks(3)
+            .filter(|t| t.iter().any(|v| v.ver_pos == dark))
+            .count();
+        assert_eq!(triangles_with_dark, 1);
+        for v in top.iter() {
+            let expected = if v.ver_pos == dark { 2.0 / 3.0 } else { 1.0 };
+            assert_eq!(v.ao, expected);
+        }
+
+        // Without ambient occlusion nothing is darkened.
+        let mesh = mesh_culled(&padded, &registry(), false);
+        assert!(mesh.vertices.iter().all(|v| v.ao == 1.0));
+    }
+
+    #[test]
+    fn separates_render_layers() {
+        let registry = BlockRegistry::parse(
+            "[stone]\ntexture = stone_xyz.png\n[leaves]\ntexture = leaves.png\ntransparent = \
+             true\n[water]\ntexture = water.png\ntranslucent = true",
+            |_| true,
+        )
+        .unwrap();
+        let (stone, leaves, water) = (Block(1), Block(2), Block(3));
+        let mut chunk = Chunk::new(Vector3::zero());
+        *chunk.block_at_mut(1, 1, 1) = stone;
+        *chunk.block_at_mut(4, 1, 1) = leaves;
+        *chunk.block_at_mut(5, 1, 1) = leaves;
+        *chunk.block_at_mut(8, 1, 1) = water;
+        *chunk.block_at_mut(9, 1, 1) = water;
+        *chunk.block_at_mut(10, 1, 1) = stone;
+        let mesh = mesh_culled(&PaddedChunk::from_chunk(&chunk), &registry, false);
+        // The stone next to the water shows its face through the water.
+        assert_eq!(
+            mesh.layer(RenderLayer::Opaque).len(),
+            12 * VERTICES_PER_FACE
+        );
+        // Leaves show their faces between each other.
+        assert_eq!(
+            mesh.layer(RenderLayer::Cutout).len(),
+            12 * VERTICES_PER_FACE
+        );
+        // Water hides the faces between water and next to stone.
+        assert_eq!(
+            mesh.layer(RenderLayer::Translucent).len(),
+            9 * VERTICES_PER_FACE
+        );
+    }
+
+    #[test]
+    fn sorts_quads_back_to_front() {
+        let chunk = chunk_with(&[(2, 2, 2), (8, 2, 2), (14, 2, 2)]);
+        let mut vertices =
+            mesh_culled(&PaddedChunk::from_chunk(&chunk), &registry(), false).vertices;
+        let quad_xs = |vertices: &[MeshVertex]| -> Vec<f32> {
+            vertices
+                .chunks(VERTICES_PER_FACE)
+                .map(|quad| quad[0].ver_pos.x.floor().min(quad[1].ver_pos.x.floor()))
+                .collect()
+        };
+
+        sort_back_to_front(&mut vertices, Vector3::new(-10.0, 2.5, 2.5));
+        let xs = quad_xs(&vertices);
+        assert_eq!(xs.len(), 18);
+        assert!(xs.windows(2).all(|w| w[0] >= w[1]));
+        assert_eq!(xs[0], 15.0);
+
+        sort_back_to_front(&mut vertices, Vector3::new(30.0, 2.5, 2.5));
+        let xs = quad_xs(&vertices);
+        assert!(xs.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(xs[0], 2.0);
+    }
+
+    #[test]
+    fn fluid_surfaces_slope_towards_lower_levels() {
+        let registry = BlockRegistry::parse(
+            "[stone]\ntexture = stone_xyz.png\n[water]\ntexture = water.png\ntranslucent = \
+             true\nfluid = true",
+            |_| true,
+        )
+        .unwrap();
+        let water = Block(2);
+        let flowing = water.with_state(4);
+        let mut chunk = Chunk::new(Vector3::zero());
+        *chunk.block_at_mut(5, 1, 5) = water;
+        *chunk.block_at_mut(6, 1, 5) = flowing;
+        // A falling column fills its lower block up to the top.
+        *chunk.block_at_mut(10, 1, 10) = flowing;
+        *chunk.block_at_mut(10, 2, 10) = water;
+        let options = MeshOptions {
+            mode: MeshMode::Greedy,
+            ambient_occlusion: true,
+        };
+        let mesh = mesh(&PaddedChunk::from_chunk(&chunk), &registry, options);
+        let vertices = mesh.layer(RenderLayer::Translucent);
+        assert_eq!(vertices.len(), 20 * VERTICES_PER_FACE);
+
+        let surface_at = |x: f32| -> Vec<f32> {
+            vertices
+                .iter()
+                .filter(|v| v.ver_pos.x == x && v.ver_pos.y > 1.0 && v.ver_pos.y < 2.0)
+                .map(|v| v.ver_pos.y - 1.0)
+                .collect()
+        };
+        let spread = registry.fluid(water).unwrap().spread;
+        let high = fluid_height(water, spread);
+        let low = fluid_height(flowing, spread);
+        assert!(low < high);
+        assert!(!surface_at(5.0).is_empty());
+        let all_near =
+            |x: f32, height: f32| surface_at(x).iter().all(|&h| (h - height).abs() < 1e-6);
+        assert!(all_near(5.0, high));
+        assert!(all_near(6.0, (high + low) / 2.0));
+        assert!(all_near(7.0, low));
+        assert!(surface_at(10.0).is_empty());
+    }
+
+    #[test]
+    fn low_blocks_are_cut_off_at_their_height() {
+        let registry = BlockRegistry::parse(
+            "[stone]\ntexture = stone_xyz.png\n[slab]\ntexture = stone_xyz.png\nheight = 0.5",
+            |_| true,
+        )
+        .unwrap();
+        let slab = Block(2);
+        let mut chunk = Chunk::new(Vector3::zero());
+        *chunk.block_at_mut(5, 1, 5) = slab;
+        *chunk.block_at_mut(6, 1, 5) = slab;
+        *chunk.block_at_mut(5, 2, 5) = STONE;
+        let options = MeshOptions {
+            mode: MeshMode::Greedy,
+            ambient_occlusion: true,
+        };
+        let mesh = mesh(&PaddedChunk::from_chunk(&chunk), &registry, options);
+        let vertices = mesh.layer(RenderLayer::Cutout);
+        // The shared side is hidden but the tops are not, even under stone.
+        assert_eq!(vertices.len(), 10 * VERTICES_PER_FACE);
+        assert!(vertices
+            .iter()
+            .all(|v| v.ver_pos.y == 1.0 || v.ver_pos.y == 1.5));
+        assert!(vertices.iter().all(|v| v.tex_pos.y <= 1.0));
+        // The bottom of the stone above the slab shows.
+        assert_eq!(mesh.layer(RenderLayer::Opaque).len(), 6 * VERTICES_PER_FACE);
+    }
+
+    #[test]
+    fn loose_blocks_are_whole_cubes() {
+        let mut vertices = Vec::new();
+        let position = Vector3::new(-3.0, 7.25, 2.0);
+        mesh_block(&mut vertices, &registry(), DIRT, position, OPEN_SKY);
+        assert_eq!(vertices.len(), 6 * VERTICES_PER_FACE);
+        for v in vertices.iter() {
+            let p = v.ver_pos - position;
+            assert!((0..3).all(|axis| p[axis] == 0.0 || p[axis] == 1.0));
+            assert_eq!(v.light, Vector2::new(1.0, 0.0));
+        }
+    }
+
+    /// The vertices of the triangles lying in the plane at height `y`.
+    fn vertices_in_plane_y(mesh: &ChunkMesh, y: f32) -> Vec<MeshVertex> {
+        mesh.vertices
+            .chunks(3)
+            .filter(|t| t.iter().all(|v| v.ver_pos.y == y))
+            .flat_map(|t| t.iter().cloned())
+            .collect()
+    }
+
+    #[test]
+    fn faces_take_the_light_in_front_of_them() {
+        let mut chunk = Chunk::new(Vector3::zero());
+        for z in 0..CHUNK_SIDE_BLOCKS {
+            for x in 0..CHUNK_SIDE_BLOCKS {
+                *chunk.block_at_mut(x, 0, z) = STONE;
+                *chunk.light_at_mut(x, 1, z) = Light::new(if x < 8 { 15 } else { 3 }, 0);
+            }
+        }
+        *chunk.light_at_mut(2, 1, 2) = Light::new(15, 6);
+        let mesh = mesh_greedy(&PaddedChunk::from_chunk(&chunk), &registry(), false);
+        let top = vertices_in_plane_y(&mesh, 1.0);
+        assert!(top.iter().any(|v| v.light == Vector2::new(1.0, 0.4)));
+        assert!(top.iter().any(|v| v.light == Vector2::new(0.2, 0.0)));
+        // Differently lit faces are not merged.
+        assert!(top.len() > VERTICES_PER_FACE * 2);
+        // The border of a lone chunk is open to the sky.
+        assert!(vertices_in_plane_y(&mesh, 0.0)
+            .iter()
+            .all(|v| v.light == Vector2::new(1.0, 0.0)));
+    }
+
+    #[test]
+    fn neighbouring_chunks_hide_border_faces() {
+        let mut world = World::new();
+        let side = CHUNK_SIDE_BLOCKS as i32;
+        world.set_block(Vector3::new(side - 1, 0, 0), STONE);
+        assert_eq!(
+            mesh_culled(
+                &PaddedChunk::new(&world, Vector3::zero()),
+                &registry(),
+                false
+            )
+            .face_count(),
+            6
+        );
+        world.set_block(Vector3::new(side, 0, 0), STONE);
+        assert_eq!(
+            mesh_culled(
+                &PaddedChunk::new(&world, Vector3::zero()),
+                &registry(),
+                false
+            )
+            .face_count(),
+            5
+        );
+        assert_eq!(
+            mesh_culled(
+                &PaddedChunk::new(&world, Vector3::new(1, 0, 0)),
+                &registry(),
+                false
+            )
+            .face_count(),
+            5
+        );
+    }
+}
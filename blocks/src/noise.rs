@@ -0,0 +1,192 @@
+/// Mixes a seed and lattice coordinates into a pseudo random number. The
+/// result only depends on the arguments so noise can be sampled in any
+/// order.
+#[inline]
+pub fn hash(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    let mut h = seed;
+    h ^= (x as u32).wrapping_mul(0x8da6_b343);
+    h ^= (y as u32).wrapping_mul(0xd816_3841);
+    h ^= (z as u32).wrapping_mul(0xcb1a_b31f);
+    // Finalizer from MurmurHash3.
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+/// Maps a hash to [0, 1).
+#[inline]
+pub fn hash_to_unit(h: u32) -> f32 {
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+#[inline]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[inline]
+fn gradient2(h: u32, dx: f32, dy: f32) -> f32 {
+    // 8 directions, the diagonals scaled to unit length.
+    const D: f32 = ::std::f32::consts::FRAC_1_SQRT_2;
+    match h & 7 {
+        0 => dx,
+        1 => -dx,
+        2 => dy,
+        3 => -dy,
+        4 => (dx + dy) * D,
+        5 => (dx - dy) * D,
+        6 => (-dx + dy) * D,
+        _ => (-dx - dy) * D,
+    }
+}
+
+#[inline]
+fn gradient3(h: u32, dx: f32, dy: f32, dz: f32) -> f32 {
+    // The 12 edge directions of a cube, 4 of them repeated.
+    match h & 15 {
+        0 | 12 => dx + dy,
+        1 | 13 => -dx + dy,
+        2 => dx - dy,
+        3 => -dx - dy,
+        4 => dx + dz,
+        5 => -dx + dz,
+        6 => dx - dz,
+        7 => -dx - dz,
+        8 => dy + dz,
+        9 | 14 => -dy + dz,
+        10 => dy - dz,
+        _ => -dy - dz,
+    }
+}
+
+/// Seeded gradient noise.
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    seed: u32,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Noise { seed }
+    }
+
+    /// Gradient noise in roughly [-1, 1] that is 0 at integer coordinates.
+    pub fn sample2(&self, x: f32, y: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (ix, iy) = (x0 as i32, y0 as i32);
+        let (dx, dy) = (x - x0, y - y0);
+        let g = |cx: i32, cy: i32| {
+            gradient2(
+                hash(self.seed, ix + cx, iy + cy, 0),
+                dx - cx as f32,
+                dy - cy as f32,
+            )
+        };
+        let (u, v) = (fade(dx), fade(dy));
+        lerp(lerp(g(0, 0), g(1, 0), u), lerp(g(0, 1), g(1, 1), u), v) * ::std::f32::consts::SQRT_2
+    }
+
+    /// Gradient noise in roughly [-1, 1] that is 0 at integer coordinates.
+    pub fn sample3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let z0 = z.floor();
+        let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+        let (dx, dy, dz) = (x - x0, y - y0, z - z0);
+        let g = |cx: i32, cy: i32, cz: i32| {
+            gradient3(
+                hash(self.seed, ix + cx, iy + cy, iz + cz),
+                dx - cx as f32,
+                dy - cy as f32,
+                dz - cz as f32,
+            )
+        };
+        let (u, v, w) = (fade(dx), fade(dy), fade(dz));
+        lerp(
+            lerp(
+                lerp(g(0, 0, 0), g(1, 0, 0), u),
+                lerp(g(0, 1, 0), g(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(g(0, 0, 1), g(1, 0, 1), u),
+                lerp(g(0, 1, 1), g(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+
+    /// Sums `octaves` layers of noise, each with twice the frequency and
+    /// half the amplitude of the previous one. The result is normalized to
+    /// roughly [-1, 1].
+    pub fn fractal2(&self, x: f32, y: f32, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut scale = 1.0;
+        for octave in 0..octaves {
+            let layer = Noise::new(self.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9)));
+            sum += layer.sample2(x * scale, y * scale) / scale;
+            total += 1.0 / scale;
+            scale *= 2.0;
+        }
+        sum / total
+    }
+
+    /// Sums `octaves` layers of noise, see `fractal2`.
+    pub fn fractal3(&self, x: f32, y: f32, z: f32, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut scale = 1.0;
+        for octave in 0..octaves {
+            let layer = Noise::new(self.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9)));
+            sum += layer.sample3(x * scale, y * scale, z * scale) / scale;
+            total += 1.0 / scale;
+            scale *= 2.0;
+        }
+        sum / total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_zero_on_lattice_points() {
+        let noise = Noise::new(7);
+        for i in -3..3 {
+            assert_eq!(noise.sample2(i as f32, (i * 5) as f32), 0.0);
+            assert_eq!(noise.sample3(i as f32, 2.0, (i * 3) as f32), 0.0);
+        }
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let noise = Noise::new(1);
+        for i in 0..1000 {
+            let x = i as f32 * 0.137 - 50.0;
+            let y = i as f32 * 0.071 + 13.0;
+            assert!(noise.sample2(x, y).abs() <= 1.0);
+            assert!(noise.sample3(x, y, x - y).abs() <= 1.0);
+            assert!(noise.fractal2(x, y, 4).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn depends_on_seed() {
+        let a = Noise::new(1).sample3(0.5, 0.25, 0.75);
+        let b = Noise::new(2).sample3(0.5, 0.25, 0.75);
+        assert_ne!(a, b);
+        assert_eq!(a, Noise::new(1).sample3(0.5, 0.25, 0.75));
+    }
+}
@@ -0,0 +1,289 @@
+use block_registry::BlockRegistry;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_SIDE_BLOCKS;
+use chunk::CHUNK_TOTAL_BLOCKS;
+use cube::Face;
+use cube::FACE_COUNT;
+use mesher::PaddedChunk;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+/// Which faces of a chunk can see each other through the blocks that are
+/// not opaque inside it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FaceConnectivity {
+    /// Per face a bit mask of the faces it is connected to.
+    faces: [u8; FACE_COUNT],
+}
+
+impl FaceConnectivity {
+    /// No face can see any other face.
+    pub fn none() -> Self {
+        FaceConnectivity {
+            faces: [0; FACE_COUNT],
+        }
+    }
+
+    /// Every face can see every other face.
+    pub fn all() -> Self {
+        FaceConnectivity {
+            faces: [(1 << FACE_COUNT) - 1; FACE_COUNT],
+        }
+    }
+
+    #[inline]
+    pub fn connects(&self, a: Face, b: Face) -> bool {
+        self.faces[a.index()] & 1 << b.index() != 0
+    }
+
+    /// Flood fills the blocks of the chunk that are not opaque. The faces
+    /// touched by each filled region can all see each other.
+    pub fn from_padded(padded: &PaddedChunk, registry: &BlockRegistry) -> Self {
+        let side = CHUNK_SIDE_BLOCKS as i32;
+        let mut connectivity = FaceConnectivity::none();
+        let mut visited = vec![false; CHUNK_TOTAL_BLOCKS];
+        let mut stack = Vec::new();
+
+        for start_index in 0..CHUNK_TOTAL_BLOCKS {
+            let start = Vector3::new(
+                (start_index % CHUNK_SIDE_BLOCKS) as i32,
+                (start_index / CHUNK_SIDE_BLOCKS % CHUNK_SIDE_BLOCKS) as i32,
+                (start_index / CHUNK_SIDE_BLOCKS / CHUNK_SIDE_BLOCKS) as i32,
+            );
+            if visited[start_index]
+                || registry.is_opaque(padded.block_at(start.x, start.y, start.z))
+            {
+                continue;
+            }
+
+            let mut touched = 0u8;
+            visited[start_index] = true;
+            stack.push(start);
+            while let Some(p) = stack.pop() {
+                for &face in Face::ALL.iter() {
+                    let q = p + face.normal();
+                    if q.x < 0 || q.x >= side || q.y < 0 || q.y >= side || q.z < 0 || q.z >= side {
+                        touched |= 1 << face.index();
+                        continue;
+                    }
+                    let index = Chunk::block_index(q.x as usize, q.y as usize, q.z as usize);
+                    if !visited[index] && !registry.is_opaque(padded.block_at(q.x, q.y, q.z)) {
+                        visited[index] = true;
+                        stack.push(q);
+                    }
+                }
+            }
+
+            for &face in Face::ALL.iter() {
+                if touched & 1 << face.index() != 0 {
+                    connectivity.faces[face.index()] |= touched;
+                }
+            }
+        }
+
+        connectivity
+    }
+}
+
+/// Skips chunks that can not be seen from the camera chunk because every
+/// line of sight to them is blocked by opaque blocks. Starting at the
+/// camera chunk, chunks are visited by stepping through a face, provided
+/// the face through which a chunk was entered can see the face it is left
+/// through. Steps never go back in a direction opposite to an earlier step
+/// which keeps the walk moving away from the camera.
+pub struct OcclusionCuller {
+    connectivity: HashMap<Vector3<i32>, FaceConnectivity>,
+}
+
+impl OcclusionCuller {
+    pub fn new() -> Self {
+        OcclusionCuller {
+            connectivity: HashMap::new(),
+        }
+    }
+
+    pub fn set_chunk(&mut self, position_indices: Vector3<i32>, connectivity: FaceConnectivity) {
+        self.connectivity.insert(position_indices, connectivity);
+    }
+
+    pub fn remove_chunk(&mut self, position_indices: Vector3<i32>) {
+        self.connectivity.remove(&position_indices);
+    }
+
+    /// The chunks that may be visible from the camera chunk. Only chunks
+    /// for which the connectivity is known and for which `in_view` returns
+    /// true are visited.
+    pub fn visible_chunks<F>(&self, camera_chunk: Vector3<i32>, in_view: F) -> HashSet<Vector3<i32>>
+    where
+        F: Fn(Vector3<i32>) -> bool,
+    {
+        let mut visible = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        // The camera can look out of its own chunk in any direction.
+        visible.insert(camera_chunk);
+        queue.push_back((camera_chunk, None, 0u8));
+
+        while let Some((position_indices, entry, directions)) = queue.pop_front() {
+            let connectivity = match self.connectivity.get(&position_indices) {
+                Some(&connectivity) => connectivity,
+                None if entry.is_none() => FaceConnectivity::all(),
+                None => continue,
+            };
+            for &exit in Face::ALL.iter() {
+                if directions & 1 << exit.opposite().index() != 0 {
+                    continue;
+                }
+                if let Some(entry) = entry {
+                    if !connectivity.connects(entry, exit) {
+                        continue;
+                    }
+                }
+                let next = position_indices + exit.normal();
+                if visible.contains(&next)
+                    || !self.connectivity.contains_key(&next)
+                    || !in_view(next)
+                {
+                    continue;
+                }
+                visible.insert(next);
+                queue.push_back((next, Some(exit.opposite()), directions | 1 << exit.index()));
+            }
+        }
+
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use world::World;
+
+    const STONE: Block = Block(1);
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::parse("[stone]\ntexture = stone_xyz.png", |_| true).unwrap()
+    }
+
+    fn connectivity_of(chunk: &Chunk) -> FaceConnectivity {
+        FaceConnectivity::from_padded(&PaddedChunk::from_chunk(chunk), &registry())
+    }
+
+    fn solid_chunk(position_indices: Vector3<i32>) -> Chunk {
+        let mut chunk = Chunk::new(position_indices);
+        chunk.blocks.fill(STONE);
+        chunk
+    }
+
+    /// A solid chunk with a straight tunnel along the x axis.
+    fn tunnel_chunk(position_indices: Vector3<i32>) -> Chunk {
+        let mut chunk = solid_chunk(position_indices);
+        for x in 0..CHUNK_SIDE_BLOCKS {
+            *chunk.block_at_mut(x, 5, 7) = Block::VOID;
+        }
+        chunk
+    }
+
+    fn culler(world: &World) -> OcclusionCuller {
+        let registry = registry();
+        let mut culler = OcclusionCuller::new();
+        for chunk in world.chunks() {
+            let padded = PaddedChunk::new(world, chunk.position_indices);
+            culler.set_chunk(
+                chunk.position_indices,
+                FaceConnectivity::from_padded(&padded, &registry),
+            );
+        }
+        culler
+    }
+
+    #[test]
+    fn connects_faces_through_open_blocks() {
+        assert_eq!(
+            connectivity_of(&Chunk::new(Vector3::zero())),
+            FaceConnectivity::all()
+        );
+        assert_eq!(
+            connectivity_of(&solid_chunk(Vector3::zero())),
+            FaceConnectivity::none()
+        );
+
+        let tunnel = connectivity_of(&tunnel_chunk(Vector3::zero()));
+        assert!(tunnel.connects(Face::NegX, Face::PosX));
+        assert!(tunnel.connects(Face::PosX, Face::NegX));
+        assert!(!tunnel.connects(Face::NegX, Face::PosY));
+        assert!(!tunnel.connects(Face::NegZ, Face::PosZ));
+
+        // A wall across the chunk separates -X from +X only.
+        let mut wall = Chunk::new(Vector3::zero());
+        for z in 0..CHUNK_SIDE_BLOCKS {
+            for y in 0..CHUNK_SIDE_BLOCKS {
+                *wall.block_at_mut(8, y, z) = STONE;
+            }
+        }
+        let wall = connectivity_of(&wall);
+        assert!(!wall.connects(Face::NegX, Face::PosX));
+        assert!(wall.connects(Face::NegX, Face::PosY));
+        assert!(wall.connects(Face::NegY, Face::PosY));
+    }
+
+    #[test]
+    fn hides_chunks_behind_solid_chunks() {
+        // A row of chunks: air, stone, air.
+        let mut world = World::new();
+        world.insert_chunk(Chunk::new(Vector3::new(0, 0, 0)));
+        world.insert_chunk(solid_chunk(Vector3::new(1, 0, 0)));
+        world.insert_chunk(Chunk::new(Vector3::new(2, 0, 0)));
+
+        let visible = culler(&world).visible_chunks(Vector3::zero(), |_| true);
+        assert!(visible.contains(&Vector3::new(1, 0, 0)));
+        assert!(!visible.contains(&Vector3::new(2, 0, 0)));
+
+        // Drilling a tunnel through the stone opens up the view.
+        world.insert_chunk(tunnel_chunk(Vector3::new(1, 0, 0)));
+        let visible = culler(&world).visible_chunks(Vector3::zero(), |_| true);
+        assert!(visible.contains(&Vector3::new(2, 0, 0)));
+    }
+
+    #[test]
+    fn hides_enclosed_underground_chunks() {
+        // A 5x5x5 block of solid chunks with a layer of air chunks on top.
+        let mut world = World::new();
+        for z in -2..=2 {
+            for y in -5..=1 {
+                for x in -2..=2 {
+                    let p = Vector3::new(x, y, z);
+                    world.insert_chunk(if y < 0 { solid_chunk(p) } else { Chunk::new(p) });
+                }
+            }
+        }
+
+        let culler = culler(&world);
+        let visible = culler.visible_chunks(Vector3::new(0, 1, 0), |_| true);
+        // The top layer of stone is visible, everything below it is not.
+        assert!(visible.contains(&Vector3::new(2, -1, -2)));
+        for z in -2..=2 {
+            for x in -2..=2 {
+                assert!(!visible.contains(&Vector3::new(x, -2, z)));
+            }
+        }
+
+        // A camera inside a solid chunk sees its direct neighbours.
+        let visible = culler.visible_chunks(Vector3::new(0, -3, 0), |_| true);
+        assert_eq!(visible.len(), 7);
+    }
+
+    #[test]
+    fn respects_the_view() {
+        let mut world = World::new();
+        for x in -3..=3 {
+            world.insert_chunk(Chunk::new(Vector3::new(x, 0, 0)));
+        }
+        let visible = culler(&world).visible_chunks(Vector3::zero(), |p| p.x >= 0);
+        assert_eq!(visible.len(), 4);
+    }
+}
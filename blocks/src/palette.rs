@@ -0,0 +1,276 @@
+use block::Block;
+use chunk::CHUNK_TOTAL_BLOCKS;
+use std::ops::{Deref, DerefMut};
+
+/// Bits per index, in increasing order. Each divides 64 so no index
+/// straddles two words. 16 bits can address every possible `Block`.
+const INDEX_BITS: [u32; 5] = [1, 2, 4, 8, 16];
+
+/// The blocks of a chunk stored as indices into a palette of the distinct
+/// blocks it contains, packed into as few bits as the palette allows. A
+/// chunk made of a single block needs no indices at all.
+#[derive(Clone, Debug)]
+pub struct PalettedBlocks {
+    palette: Vec<Block>,
+    /// Bits per index, 0 when the palette only contains one block.
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl PalettedBlocks {
+    pub fn new(block: Block) -> Self {
+        PalettedBlocks {
+            palette: vec![block],
+            bits: 0,
+            words: Vec::new(),
+        }
+    }
+
+    /// Whether every block is the same.
+    #[inline]
+    pub fn is_uniform(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Bits used per block.
+    #[inline]
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// The distinct blocks, including ones that are no longer used until
+    /// the next call to `compact`.
+    pub fn palette(&self) -> &[Block] {
+        &self.palette
+    }
+
+    #[inline]
+    fn index_at(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        (self.words[i / per_word] >> shift & mask) as usize
+    }
+
+    #[inline]
+    fn set_index_at(&mut self, i: usize, index: usize) {
+        let per_word = 64 / self.bits as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        let word = &mut self.words[i / per_word];
+        *word = *word & !(mask << shift) | (index as u64) << shift;
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize) -> &Block {
+        &self.palette[self.index_at(i)]
+    }
+
+    /// A copy of the block at `i` that is written back when dropped.
+    #[inline]
+    pub fn get_mut(&mut self, i: usize) -> BlockMut {
+        let block = *self.get(i);
+        BlockMut {
+            blocks: self,
+            index: i,
+            original: block,
+            block,
+        }
+    }
+
+    pub fn set(&mut self, i: usize, block: Block) {
+        debug_assert!(i < CHUNK_TOTAL_BLOCKS);
+        let index = match self.palette.iter().position(|&b| b == block) {
+            Some(index) => index,
+            None => {
+                if self.palette.len() == self.capacity() {
+                    // Drop unused blocks before resorting to more bits.
+                    self.compact();
+                    if self.palette.len() == self.capacity() {
+                        let bits = self.bits_for(self.palette.len() + 1);
+                        self.repack(bits, |index| index);
+                    }
+                }
+                self.palette.push(block);
+                self.palette.len() - 1
+            }
+        };
+        if self.bits != 0 {
+            self.set_index_at(i, index);
+        }
+    }
+
+    /// Sets every block.
+    pub fn fill(&mut self, block: Block) {
+        *self = PalettedBlocks::new(block);
+    }
+
+    /// Number of palette entries the current indices can address.
+    #[inline]
+    fn capacity(&self) -> usize {
+        1 << self.bits
+    }
+
+    fn bits_for(&self, palette_len: usize) -> u32 {
+        if palette_len <= 1 {
+            0
+        } else {
+            *INDEX_BITS
+                .iter()
+                .find(|&&bits| palette_len <= 1 << bits)
+                .unwrap()
+        }
+    }
+
+    /// Rewrites the indices with `bits` bits per index, mapping each old
+    /// index through `map`.
+    fn repack<F>(&mut self, bits: u32, map: F)
+    where
+        F: Fn(usize) -> usize,
+    {
+        let indices: Vec<usize> = (0..CHUNK_TOTAL_BLOCKS)
+            .map(|i| map(self.index_at(i)))
+            .collect();
+        self.bits = bits;
+        self.words = if bits == 0 {
+            Vec::new()
+        } else {
+            vec![0; CHUNK_TOTAL_BLOCKS * bits as usize / 64]
+        };
+        if bits != 0 {
+            for (i, index) in indices.into_iter().enumerate() {
+                self.set_index_at(i, index);
+            }
+        }
+    }
+
+    /// Removes unused blocks from the palette and uses as few bits per
+    /// index as possible. Chunks made of a single block become uniform.
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..CHUNK_TOTAL_BLOCKS {
+            used[self.index_at(i)] = true;
+        }
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (index, &block) in self.palette.iter().enumerate() {
+            if used[index] {
+                remap[index] = palette.len();
+                palette.push(block);
+            }
+        }
+        let bits = self.bits_for(palette.len());
+        if palette.len() != self.palette.len() || bits != self.bits {
+            self.repack(bits, |index| remap[index]);
+            self.palette = palette;
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Block> + 'a {
+        (0..CHUNK_TOTAL_BLOCKS).map(move |i| *self.get(i))
+    }
+}
+
+/// Mutable access to a block in `PalettedBlocks`, which can not hand out
+/// references into its packed indices.
+pub struct BlockMut<'a> {
+    blocks: &'a mut PalettedBlocks,
+    index: usize,
+    original: Block,
+    block: Block,
+}
+
+impl<'a> Deref for BlockMut<'a> {
+    type Target = Block;
+
+    fn deref(&self) -> &Block {
+        &self.block
+    }
+}
+
+impl<'a> DerefMut for BlockMut<'a> {
+    fn deref_mut(&mut self) -> &mut Block {
+        &mut self.block
+    }
+}
+
+impl<'a> Drop for BlockMut<'a> {
+    fn drop(&mut self) {
+        if self.block != self.original {
+            self.blocks.set(self.index, self.block);
+        }
+    }
+}
+
+impl PartialEq for PalettedBlocks {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noise;
+
+    #[test]
+    fn uniform_blocks_need_no_indices() {
+        let mut blocks = PalettedBlocks::new(Block::VOID);
+        assert!(blocks.is_uniform());
+        assert!(blocks.words.is_empty());
+        assert!(blocks.iter().all(|b| b == Block::VOID));
+
+        *blocks.get_mut(3) = Block(1);
+        assert_eq!(blocks.bits(), 1);
+        assert_eq!(*blocks.get(3), Block(1));
+        assert_eq!(*blocks.get(4), Block::VOID);
+
+        for i in 0..CHUNK_TOTAL_BLOCKS {
+            blocks.set(i, Block(1));
+        }
+        blocks.compact();
+        assert!(blocks.is_uniform());
+        assert_eq!(blocks.palette(), &[Block(1)]);
+    }
+
+    #[test]
+    fn matches_flat_array() {
+        let mut blocks = PalettedBlocks::new(Block::VOID);
+        let mut flat = [Block::VOID; CHUNK_TOTAL_BLOCKS];
+        for n in 0..20_000 {
+            let h = noise::hash(5, n, 0, 0);
+            let i = h as usize % CHUNK_TOTAL_BLOCKS;
+            // Few distinct blocks at first, then many.
+            let block = Block((h >> 16) as u16 % if n < 10_000 { 3 } else { 1000 });
+            blocks.set(i, block);
+            flat[i] = block;
+        }
+        assert!(blocks.iter().eq(flat.iter().cloned()));
+    }
+
+    #[test]
+    fn holds_more_than_256_distinct_blocks() {
+        let mut blocks = PalettedBlocks::new(Block::VOID);
+        for i in 0..CHUNK_TOTAL_BLOCKS {
+            blocks.set(i, Block::new(i % 1000, (i / 1000) as u8));
+        }
+        assert_eq!(blocks.bits(), 16);
+        for i in 0..CHUNK_TOTAL_BLOCKS {
+            assert_eq!(*blocks.get(i), Block::new(i % 1000, (i / 1000) as u8));
+        }
+    }
+
+    #[test]
+    fn reuses_unused_palette_entries() {
+        let mut blocks = PalettedBlocks::new(Block::VOID);
+        for id in 1..100 {
+            blocks.set(0, Block(id));
+        }
+        // Only two blocks are in use at any time.
+        assert!(blocks.bits() <= 2);
+        assert_eq!(*blocks.get(0), Block(99));
+    }
+}
@@ -0,0 +1,267 @@
+use cgmath::*;
+use collision::sweep;
+use collision::Aabb;
+use collision::Movement;
+
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+/// Height of the eyes above the feet.
+pub const EYE_HEIGHT: f32 = 1.62;
+/// Ledges up to this high, like the tops of blocks lower than a full
+/// block, are walked onto without jumping. Full blocks are higher, so
+/// climbing them takes a jump.
+pub const STEP_HEIGHT: f32 = 0.6;
+
+/// Walking speed in blocks per second.
+const WALK_SPEED: f32 = 4.5;
+/// Downward acceleration in blocks per second squared.
+const GRAVITY: f32 = 32.0;
+/// Upward speed at the start of a jump, enough to jump onto a block.
+const JUMP_SPEED: f32 = 9.0;
+const MAX_FALL_SPEED: f32 = 60.0;
+
+/// A player that walks through the world with an axis aligned bounding box
+/// and is pulled down by gravity.
+pub struct Player {
+    /// Centre of the bottom of the bounding box.
+    pub position: Vector3<f32>,
+    /// In blocks per second.
+    pub velocity: Vector3<f32>,
+    pub on_ground: bool,
+}
+
+impl Player {
+    pub fn new(position: Vector3<f32>) -> Self {
+        Player {
+            position,
+            velocity: Vector3::zero(),
+            on_ground: false,
+        }
+    }
+
+    /// A player whose eyes are at `eye_position`.
+    pub fn from_eye_position(eye_position: Vector3<f32>) -> Self {
+        Player::new(eye_position - Vector3::unit_y() * EYE_HEIGHT)
+    }
+
+    pub fn eye_position(&self) -> Vector3<f32> {
+        self.position + Vector3::unit_y() * EYE_HEIGHT
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let half_width = PLAYER_WIDTH / 2.0;
+        Aabb::new(
+            self.position - Vector3::new(half_width, 0.0, half_width),
+            self.position + Vector3::new(half_width, PLAYER_HEIGHT, half_width),
+        )
+    }
+
+    /// Advances the player by `delta_time` seconds. `direction` is the
+    /// direction to walk in, in world space, with a length of at most 1.
+    /// Only its x and z components are used. The player jumps if `jump` is
+    /// set while standing on the ground. `solid_height` tells how high each
+    /// block stops the player above its bottom, see `collision::sweep`.
+    pub fn update<F>(
+        &mut self,
+        delta_time: f32,
+        direction: Vector3<f32>,
+        jump: bool,
+        solid_height: F,
+    ) where
+        F: Fn(Vector3<i32>) -> f32,
+    {
+        let walk = Vector3::new(direction.x, 0.0, direction.z);
+        let walk = if walk.magnitude2() > 1.0 {
+            walk.normalize()
+        } else {
+            walk
+        } * WALK_SPEED;
+        self.velocity.x = walk.x;
+        self.velocity.z = walk.z;
+        if jump && self.on_ground {
+            self.velocity.y = JUMP_SPEED;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * delta_time).max(-MAX_FALL_SPEED);
+
+        let displacement = self.velocity * delta_time;
+        let aabb = self.aabb();
+        let mut movement = sweep(aabb, displacement, &solid_height);
+
+        if self.on_ground && (movement.blocked[0] || movement.blocked[2]) {
+            if let Some(stepped) = step_up(aabb, displacement, &solid_height) {
+                let horizontal_distance2 =
+                    |m: &Movement| m.displacement.x.powi(2) + m.displacement.z.powi(2);
+                if horizontal_distance2(&stepped) > horizontal_distance2(&movement) {
+                    movement = stepped;
+                }
+            }
+        }
+
+        self.position += movement.displacement;
+        self.on_ground = movement.blocked[1] && displacement.y < 0.0;
+        for axis in 0..3 {
+            if movement.blocked[axis] {
+                self.velocity[axis] = 0.0;
+            }
+        }
+    }
+}
+
+/// Tries to move over a ledge by moving up, then horizontally and then
+/// down again onto whatever is there.
+fn step_up<F>(aabb: Aabb, displacement: Vector3<f32>, solid_height: &F) -> Option<Movement>
+where
+    F: Fn(Vector3<i32>) -> f32,
+{
+    let up = sweep(aabb, Vector3::unit_y() * STEP_HEIGHT, solid_height);
+    if up.displacement.y <= 0.0 {
+        return None;
+    }
+    let raised = aabb.translate(up.displacement);
+    let across = sweep(
+        raised,
+        Vector3::new(displacement.x, 0.0, displacement.z),
+        solid_height,
+    );
+    let moved = raised.translate(across.displacement);
+    let down = sweep(
+        moved,
+        Vector3::unit_y() * (displacement.y.min(0.0) - up.displacement.y),
+        solid_height,
+    );
+    Some(Movement {
+        displacement: up.displacement + across.displacement + down.displacement,
+        blocked: [across.blocked[0], down.blocked[1], across.blocked[2]],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const DT: f32 = 1.0 / 100.0;
+
+    /// A floor at y = 0 from x, z = -10 to 10 with extra full blocks on
+    /// top. Maps blocks to their height.
+    fn world(blocks: &[(i32, i32, i32)]) -> HashMap<Vector3<i32>, f32> {
+        let mut solid: HashMap<Vector3<i32>, f32> = blocks
+            .iter()
+            .map(|&(x, y, z)| (Vector3::new(x, y, z), 1.0))
+            .collect();
+        for z in -10..10 {
+            for x in -10..10 {
+                solid.insert(Vector3::new(x, 0, z), 1.0);
+            }
+        }
+        solid
+    }
+
+    fn height_in<'a>(solid: &'a HashMap<Vector3<i32>, f32>) -> impl Fn(Vector3<i32>) -> f32 + 'a {
+        move |p| solid.get(&p).cloned().unwrap_or(0.0)
+    }
+
+    fn run(
+        player: &mut Player,
+        solid: &HashMap<Vector3<i32>, f32>,
+        steps: usize,
+        direction: Vector3<f32>,
+        jump: bool,
+    ) {
+        for _ in 0..steps {
+            player.update(DT, direction, jump, height_in(solid));
+        }
+    }
+
+    #[test]
+    fn falls_onto_the_ground() {
+        let solid = world(&[]);
+        let mut player = Player::new(Vector3::new(0.5, 5.0, 0.5));
+        run(&mut player, &solid, 100, Vector3::zero(), false);
+        assert!(player.on_ground);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+        assert_eq!(player.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn jumps_about_one_block_high() {
+        let solid = world(&[]);
+        let mut player = Player::new(Vector3::new(0.5, 1.0, 0.5));
+        run(&mut player, &solid, 1, Vector3::zero(), false);
+        assert!(player.on_ground);
+
+        let mut highest = 0.0f32;
+        player.update(DT, Vector3::zero(), true, height_in(&solid));
+        assert!(!player.on_ground);
+        for _ in 0..100 {
+            player.update(DT, Vector3::zero(), false, height_in(&solid));
+            highest = highest.max(player.position.y);
+        }
+        assert!(highest > 2.1 && highest < 2.5);
+        assert!(player.on_ground);
+    }
+
+    #[test]
+    fn climbs_single_blocks_by_jumping_but_not_walls() {
+        let mut solid = world(&[]);
+        for x in 3..8 {
+            solid.insert(Vector3::new(x, 1, 0), 1.0);
+        }
+        let mut player = Player::new(Vector3::new(0.5, 1.0, 0.5));
+        run(&mut player, &solid, 100, Vector3::unit_x(), false);
+        assert!((player.position.x - (3.0 - PLAYER_WIDTH / 2.0)).abs() < 1e-4);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+        run(&mut player, &solid, 10, Vector3::unit_x(), true);
+        run(&mut player, &solid, 60, Vector3::unit_x(), false);
+        assert!(player.on_ground);
+        assert!(player.position.x > 4.0);
+        assert!((player.position.y - 2.0).abs() < 1e-4);
+
+        let solid = world(&[(3, 1, 0), (3, 2, 0), (3, 3, 0)]);
+        let mut player = Player::new(Vector3::new(0.5, 1.0, 0.5));
+        run(&mut player, &solid, 100, Vector3::unit_x(), true);
+        assert!((player.position.x - (3.0 - PLAYER_WIDTH / 2.0)).abs() < 1e-4);
+        assert!(player.position.y < 2.5);
+    }
+
+    #[test]
+    fn steps_onto_lower_blocks_without_jumping() {
+        // Slabs half a block high from x = 3 to 8.
+        let mut solid = world(&[]);
+        for x in 3..8 {
+            solid.insert(Vector3::new(x, 1, 0), 0.5);
+        }
+        let mut player = Player::new(Vector3::new(0.5, 1.0, 0.5));
+        run(&mut player, &solid, 1, Vector3::zero(), false);
+        assert!(player.on_ground);
+        run(&mut player, &solid, 100, Vector3::unit_x(), false);
+        assert!(player.on_ground);
+        assert!(player.position.x > 4.0);
+        assert!((player.position.y - 1.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn does_not_step_up_while_in_the_air() {
+        let solid = world(&[(3, 3, 0)]);
+        let mut player = Player::new(Vector3::new(2.68, 3.2, 0.5));
+        player.update(DT, Vector3::unit_x(), false, height_in(&solid));
+        assert!((player.position.x - (3.0 - PLAYER_WIDTH / 2.0)).abs() < 1e-4);
+        assert!(player.position.y < 3.2);
+    }
+
+    #[test]
+    fn slides_along_walls_while_walking() {
+        // A wall two blocks high at x = 2.
+        let mut solid = world(&[]);
+        for z in -3..2 {
+            solid.insert(Vector3::new(2, 1, z), 1.0);
+            solid.insert(Vector3::new(2, 2, z), 1.0);
+        }
+        let mut player = Player::new(Vector3::new(0.5, 1.0, 0.5));
+        let direction = Vector3::new(1.0, 0.0, -1.0).normalize();
+        run(&mut player, &solid, 50, direction, false);
+        assert!((player.position.x - (2.0 - PLAYER_WIDTH / 2.0)).abs() < 1e-4);
+        assert!(player.position.z < -1.0);
+        assert!((player.position.y - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,203 @@
+use block::Block;
+use cgmath::*;
+use cube::Face;
+use world::World;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    /// World space position of the block that was hit.
+    pub position: Vector3<i32>,
+    /// The face of the hit block through which the ray entered.
+    pub face: Face,
+    /// Distance from the ray origin to the point where the ray entered
+    /// the block.
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    #[inline]
+    pub fn normal(&self) -> Vector3<i32> {
+        self.face.normal()
+    }
+
+    /// Position of the block adjacent to the hit face.
+    #[inline]
+    pub fn adjacent_position(&self) -> Vector3<i32> {
+        self.position + self.face.normal()
+    }
+}
+
+/// Walks the blocks pierced by a ray in order using the voxel traversal
+/// algorithm by Amanatides and Woo and returns the first block for which
+/// `is_hit` returns true. The block at (x, y, z) spans [x, x + 1] along
+/// each axis. The block containing the origin is never reported.
+pub fn raycast<F>(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+    mut is_hit: F,
+) -> Option<RaycastHit>
+where
+    F: FnMut(Vector3<i32>) -> bool,
+{
+    let direction = direction.normalize();
+
+    let mut position = Vector3 {
+        x: origin.x.floor() as i32,
+        y: origin.y.floor() as i32,
+        z: origin.z.floor() as i32,
+    };
+
+    let mut step = Vector3::<i32>::zero();
+    let mut t_delta = Vector3::<f32>::zero();
+    let mut t_max = Vector3::<f32>::zero();
+
+    for axis in 0..3 {
+        let d = direction[axis];
+        if d > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = 1.0 / d;
+            t_max[axis] = (position[axis] as f32 + 1.0 - origin[axis]) / d;
+        } else if d < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -1.0 / d;
+            t_max[axis] = (position[axis] as f32 - origin[axis]) / d;
+        } else {
+            step[axis] = 0;
+            t_delta[axis] = ::std::f32::INFINITY;
+            t_max[axis] = ::std::f32::INFINITY;
+        }
+    }
+
+    loop {
+        let axis = if t_max.x < t_max.y {
+            if t_max.x < t_max.z {
+                0
+            } else {
+                2
+            }
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+
+        let distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        position[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+
+        if is_hit(position) {
+            return Some(RaycastHit {
+                position,
+                // Moving in the positive direction means we entered the
+                // block through its negative face and vice versa.
+                face: Face::from_axis(axis, step[axis] < 0),
+                distance,
+            });
+        }
+    }
+}
+
+/// Finds the first non-void block along a ray through the world.
+pub fn raycast_world(
+    world: &World,
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    raycast(origin, direction, max_distance, |position| {
+        world.block_at(position) != Block::VOID
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: Block = Block(1);
+
+    fn world_with(blocks: &[(i32, i32, i32)]) -> World {
+        let mut world = World::new();
+        for &(x, y, z) in blocks {
+            world.set_block(Vector3::new(x, y, z), STONE);
+        }
+        world
+    }
+
+    #[test]
+    fn hits_block_along_positive_axis() {
+        let world = world_with(&[(3, 0, 0)]);
+        let hit = raycast_world(
+            &world,
+            Vector3::new(0.5, 0.5, 0.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(hit.position, Vector3::new(3, 0, 0));
+        assert_eq!(hit.face, Face::NegX);
+        assert_eq!(hit.adjacent_position(), Vector3::new(2, 0, 0));
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hits_block_along_negative_axis_across_chunks() {
+        let world = world_with(&[(0, -20, 0)]);
+        let hit = raycast_world(
+            &world,
+            Vector3::new(0.5, 0.5, 0.5),
+            Vector3::new(0.0, -1.0, 0.0),
+            100.0,
+        )
+        .unwrap();
+        assert_eq!(hit.position, Vector3::new(0, -20, 0));
+        assert_eq!(hit.face, Face::PosY);
+        assert!((hit.distance - 19.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_ray_enters_through_the_right_face() {
+        let world = world_with(&[(2, 1, 0)]);
+        let hit = raycast_world(
+            &world,
+            Vector3::new(0.5, 0.25, 0.5),
+            Vector3::new(1.0, 0.5, 0.0),
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(hit.position, Vector3::new(2, 1, 0));
+        assert_eq!(hit.face, Face::NegX);
+    }
+
+    #[test]
+    fn misses_blocks_beyond_max_distance() {
+        let world = world_with(&[(3, 0, 0)]);
+        assert_eq!(
+            raycast_world(
+                &world,
+                Vector3::new(0.5, 0.5, 0.5),
+                Vector3::new(1.0, 0.0, 0.0),
+                2.0,
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_block_containing_origin() {
+        let world = world_with(&[(0, 0, 0), (0, 0, 2)]);
+        let hit = raycast_world(
+            &world,
+            Vector3::new(0.5, 0.5, 0.5),
+            Vector3::new(0.0, 0.0, 1.0),
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(hit.position, Vector3::new(0, 0, 2));
+        assert_eq!(hit.face, Face::NegZ);
+    }
+}
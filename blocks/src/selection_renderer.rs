@@ -0,0 +1,274 @@
+use assets::Assets;
+use cgmath::*;
+use cgmath_ext::*;
+use gl;
+use glw;
+use glw::prelude::*;
+use program::*;
+use renderer;
+use shader::*;
+
+// Grow the box a little so the lines are not hidden by the block faces.
+const OFF: f32 = 0.005;
+
+static VERTEX_DATA: [Vector3<f32>; 8] = [
+    Vector3 {
+        x: -OFF,
+        y: -OFF,
+        z: -OFF,
+    },
+    Vector3 {
+        x: 1.0 + OFF,
+        y: -OFF,
+        z: -OFF,
+    },
+    Vector3 {
+        x: -OFF,
+        y: 1.0 + OFF,
+        z: -OFF,
+    },
+    Vector3 {
+        x: 1.0 + OFF,
+        y: 1.0 + OFF,
+        z: -OFF,
+    },
+    Vector3 {
+        x: -OFF,
+        y: -OFF,
+        z: 1.0 + OFF,
+    },
+    Vector3 {
+        x: 1.0 + OFF,
+        y: -OFF,
+        z: 1.0 + OFF,
+    },
+    Vector3 {
+        x: -OFF,
+        y: 1.0 + OFF,
+        z: 1.0 + OFF,
+    },
+    Vector3 {
+        x: 1.0 + OFF,
+        y: 1.0 + OFF,
+        z: 1.0 + OFF,
+    },
+];
+
+// The 12 edges of the box as pairs of vertex indices.
+static ELEMENT_DATA: [[u32; 2]; 12] = [
+    // Along x.
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    // Along y.
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    // Along z.
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+pub struct SelectionRendererChanges {
+    pub vert: bool,
+    pub frag: bool,
+}
+
+impl SelectionRendererChanges {
+    pub fn new() -> Self {
+        SelectionRendererChanges {
+            vert: false,
+            frag: false,
+        }
+    }
+
+    pub fn all() -> Self {
+        SelectionRendererChanges {
+            vert: true,
+            frag: true,
+        }
+    }
+}
+
+/// Draws the outline of a single block.
+pub struct SelectionRenderer {
+    program: Program,
+    vertex_shader: VertexShader,
+    fragment_shader: FragmentShader,
+    pos_from_obj_to_clp_space_loc: Option<glw::UniformLocation<[f32; 16]>>,
+    vertex_array_name: glw::VertexArrayName,
+    #[allow(unused)]
+    vertex_buffer_name: glw::BufferName,
+    #[allow(unused)]
+    element_buffer_name: glw::BufferName,
+}
+
+impl SelectionRenderer {
+    pub unsafe fn new(assets: &Assets) -> Self {
+        let program_name = glw::create_program().unwrap();
+        let vertex_shader_name = glw::create_shader(glw::VERTEX_SHADER).unwrap();
+        let fragment_shader_name = glw::create_shader(glw::FRAGMENT_SHADER).unwrap();
+
+        glw::attach_shader(&program_name, vertex_shader_name.as_ref());
+        glw::attach_shader(&program_name, fragment_shader_name.as_ref());
+
+        let [vertex_buffer_name, element_buffer_name] =
+            glw::gen_buffers_move::<[_; 2]>().unwrap_all().unwrap();
+
+        let [vertex_array_name] = glw::gen_vertex_arrays_move::<[_; 1]>()
+            .unwrap_all()
+            .unwrap();
+
+        let mut r = SelectionRenderer {
+            program: Program::Unlinked(program_name),
+            vertex_shader: VertexShader::Uncompiled(vertex_shader_name),
+            fragment_shader: FragmentShader::Uncompiled(fragment_shader_name),
+            pos_from_obj_to_clp_space_loc: None,
+            vertex_array_name,
+            vertex_buffer_name,
+            element_buffer_name,
+        };
+
+        r.update(assets, SelectionRendererChanges::all());
+
+        r
+    }
+
+    pub unsafe fn update(&mut self, assets: &Assets, changes: SelectionRendererChanges) {
+        if changes.vert {
+            renderer::recompile_and_log_vert(
+                &assets.selection_renderer_vert,
+                &mut self.vertex_shader,
+            );
+        }
+
+        if changes.frag {
+            renderer::recompile_and_log_frag(
+                &assets.selection_renderer_frag,
+                &mut self.fragment_shader,
+            );
+        }
+
+        if (changes.vert || changes.frag)
+            && if let VertexShader::Compiled(_) = self.vertex_shader {
+                true
+            } else {
+                false
+            }
+            && if let FragmentShader::Compiled(_) = self.fragment_shader {
+                true
+            } else {
+                false
+            } {
+            self.program.link();
+
+            match self.program {
+                Program::Unlinked(ref program_name) => {
+                    let log = String::from_utf8(glw::get_program_info_log_move(program_name))
+                        .expect("Program info log is not valid utf8.");
+                    eprintln!("\nFailed to link program:\n{}", log);
+                }
+                Program::Linked(ref program_name) => {
+                    glw::use_program(&program_name);
+
+                    self.pos_from_obj_to_clp_space_loc = glw::get_uniform_location(
+                        &program_name,
+                        static_cstr!("pos_from_obj_to_clp_space"),
+                    );
+                    if self.pos_from_obj_to_clp_space_loc.is_none() {
+                        eprintln!(
+                            "selection_renderer.rs: Could not find uniform location \
+                             \"pos_from_obj_to_clp_space\"."
+                        );
+                    }
+
+                    glw::bind_vertex_array(&self.vertex_array_name);
+
+                    // Set up vertex buffer.
+                    glw::bind_buffer(glw::ARRAY_BUFFER, &self.vertex_buffer_name);
+
+                    gl::BufferData(
+                        gl::ARRAY_BUFFER,
+                        ::std::mem::size_of_val(&VERTEX_DATA) as isize,
+                        VERTEX_DATA.as_ptr() as *const ::std::os::raw::c_void,
+                        gl::STATIC_DRAW,
+                    );
+
+                    match glw::get_attrib_location(&program_name, static_cstr!("vs_ver_pos")) {
+                        Some(loc) => {
+                            gl::EnableVertexAttribArray(loc.as_u32());
+                            gl::VertexAttribPointer(
+                                loc.as_u32(),                                 // index
+                                3,                                            // size (component count)
+                                gl::FLOAT,                                    // type (component type)
+                                gl::FALSE,                                    // normalized
+                                ::std::mem::size_of::<Vector3<f32>>() as i32, // stride
+                                0 as *const ::std::os::raw::c_void,           // offset
+                            );
+                        }
+                        None => {
+                            eprintln!(
+                                "selection_renderer.rs: Could not find attribute location \
+                                 \"vs_ver_pos\"."
+                            );
+                        }
+                    }
+
+                    // Set up element buffer.
+                    glw::bind_buffer(glw::ELEMENT_ARRAY_BUFFER, &self.element_buffer_name);
+
+                    gl::BufferData(
+                        gl::ELEMENT_ARRAY_BUFFER,
+                        ::std::mem::size_of_val(&ELEMENT_DATA) as isize,
+                        ELEMENT_DATA.as_ptr() as *const ::std::os::raw::c_void,
+                        gl::STATIC_DRAW,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Outlines the block at `position`. Expects depth testing to be
+    /// enabled so the outline is hidden behind other blocks.
+    pub unsafe fn render(&self, pos_from_wld_to_clp_space: &Matrix4<f32>, position: Vector3<i32>) {
+        if let Program::Linked(ref program_name) = self.program {
+            glw::use_program(program_name);
+
+            if let Some(ref loc) = self.pos_from_obj_to_clp_space_loc {
+                let pos_from_obj_to_clp_space = pos_from_wld_to_clp_space
+                    * Matrix4::from_translation(position.cast::<f32>().unwrap());
+                glw::uniform_matrix4f(loc, pos_from_obj_to_clp_space.as_matrix_ref());
+            }
+
+            glw::bind_vertex_array(&self.vertex_array_name);
+
+            gl::DrawElements(
+                gl::LINES,                          // mode
+                (ELEMENT_DATA.len() * 2) as i32,    // count
+                gl::UNSIGNED_INT,                   // index type
+                0 as *const ::std::os::raw::c_void, // offset
+            );
+        }
+    }
+
+    pub unsafe fn delete(self) {
+        let SelectionRenderer {
+            program,
+            vertex_shader,
+            fragment_shader,
+            vertex_array_name,
+            vertex_buffer_name,
+            element_buffer_name,
+            ..
+        } = self;
+        fragment_shader.delete();
+        vertex_shader.delete();
+        program.delete();
+        glw::delete_vertex_arrays_move([vertex_array_name].wrap_all());
+        glw::delete_buffers_move([vertex_buffer_name, element_buffer_name].wrap_all());
+    }
+}
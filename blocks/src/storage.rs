@@ -0,0 +1,483 @@
+//! On-disk chunk format.
+//!
+//! A chunk is stored as:
+//!
+//! ```text
+//! u16 version
+//! u16 palette length, then per entry:
+//!     u8 name length, name bytes, u8 block state
+//! runs of (u16 length, u16 palette index) covering all blocks in
+//!     `Chunk::block_index` order
+//! u8 biome per column
+//! ```
+//!
+//! Blocks are stored by name so the files stay valid when the block
+//! definitions are reordered. All integers are little endian.
+//!
+//! Chunks are grouped into region files of `REGION_SIDE_CHUNKS`^3 chunks.
+//! A region file starts with a magic number, a version and a table with the
+//! offset and length of every chunk in the region. An offset of 0 means the
+//! chunk is absent. Saving a chunk writes it to the first gap between the
+//! other chunks that is large enough, or after the last one, and cuts the
+//! file after the last chunk. This reuses the space of old copies, so a
+//! region file stays about as large as the chunks in it.
+
+use biome::Biome;
+use biome::BIOME_COUNT;
+use block::Block;
+use block_registry::BlockRegistry;
+use block_registry::MAX_BLOCK_NAME_BYTES;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_TOTAL_BLOCKS;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub const CHUNK_FORMAT_VERSION: u16 = 1;
+pub const REGION_FORMAT_VERSION: u16 = 1;
+
+const REGION_MAGIC: &[u8; 4] = b"BLKR";
+pub const REGION_SIDE_BITS: usize = 3;
+pub const REGION_SIDE_CHUNKS: usize = 1 << REGION_SIDE_BITS;
+pub const REGION_SIDE_MASK: usize = REGION_SIDE_CHUNKS - 1;
+pub const REGION_TOTAL_CHUNKS: usize = REGION_SIDE_CHUNKS * REGION_SIDE_CHUNKS * REGION_SIDE_CHUNKS;
+const REGION_HEADER_BYTES: usize = 4 + 2 + REGION_TOTAL_CHUNKS * 8;
+
+fn invalid_data<T>(message: String) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    write_u16(out, value as u16);
+    write_u16(out, (value >> 16) as u16);
+}
+
+/// Reads little endian integers from a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn read_bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() - self.position < count {
+            return invalid_data(String::from("Unexpected end of chunk data."));
+        }
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let b = self.read_bytes(2)?;
+        Ok(b[0] as u16 | (b[1] as u16) << 8)
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(self.read_u16()? as u32 | (self.read_u16()? as u32) << 16)
+    }
+}
+
+pub fn encode_chunk(chunk: &Chunk, registry: &BlockRegistry) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_u16(&mut out, CHUNK_FORMAT_VERSION);
+
+    // Palette in order of first appearance.
+    let mut palette: Vec<Block> = Vec::new();
+    let indices: Vec<u16> = chunk
+        .blocks
+        .iter()
+        .map(|block| match palette.iter().position(|&b| b == block) {
+            Some(index) => index as u16,
+            None => {
+                palette.push(block);
+                (palette.len() - 1) as u16
+            }
+        })
+        .collect();
+
+    write_u16(&mut out, palette.len() as u16);
+    for &block in palette.iter() {
+        // The registry does not allow longer names.
+        let name = registry.get(block).name.as_bytes();
+        debug_assert!(name.len() <= MAX_BLOCK_NAME_BYTES);
+        out.push(name.len() as u8);
+        out.extend_from_slice(name);
+        out.push(block.state());
+    }
+
+    let mut i = 0;
+    while i < indices.len() {
+        let index = indices[i];
+        let mut length = 1;
+        while i + length < indices.len() && indices[i + length] == index {
+            length += 1;
+        }
+        write_u16(&mut out, length as u16);
+        write_u16(&mut out, index);
+        i += length;
+    }
+
+    out.extend(chunk.biomes.iter().map(|&biome| biome as u8));
+
+    out
+}
+
+pub fn decode_chunk(
+    position_indices: Vector3<i32>,
+    bytes: &[u8],
+    registry: &BlockRegistry,
+) -> io::Result<Chunk> {
+    let mut reader = Reader::new(bytes);
+
+    let version = reader.read_u16()?;
+    if version != CHUNK_FORMAT_VERSION {
+        return invalid_data(format!("Unsupported chunk format version {}.", version));
+    }
+
+    let palette_len = reader.read_u16()? as usize;
+    let mut palette = Vec::with_capacity(palette_len);
+    for _ in 0..palette_len {
+        let name_len = reader.read_u8()? as usize;
+        let name = ::std::str::from_utf8(reader.read_bytes(name_len)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let state = reader.read_u8()?;
+        match registry.block(name) {
+            Some(block) => palette.push(block.with_state(state)),
+            None => return invalid_data(format!("Unknown block {:?}.", name)),
+        }
+    }
+
+    let mut chunk = Chunk::new(position_indices);
+    let mut i = 0;
+    while i < CHUNK_TOTAL_BLOCKS {
+        let length = reader.read_u16()? as usize;
+        let index = reader.read_u16()? as usize;
+        if length == 0 || i + length > CHUNK_TOTAL_BLOCKS || index >= palette.len() {
+            return invalid_data(String::from("Invalid block run."));
+        }
+        for j in i..i + length {
+            chunk.blocks.set(j, palette[index]);
+        }
+        i += length;
+    }
+    chunk.blocks.compact();
+
+    for biome in chunk.biomes.iter_mut() {
+        let index = reader.read_u8()? as usize;
+        if index >= BIOME_COUNT {
+            return invalid_data(format!("Unknown biome {}.", index));
+        }
+        *biome = Biome::ALL[index];
+    }
+
+    Ok(chunk)
+}
+
+/// Splits chunk position indices into region position indices and the
+/// index of the chunk within the region.
+pub fn split_chunk_position(position_indices: Vector3<i32>) -> (Vector3<i32>, usize) {
+    let r = position_indices.map(|c| c >> REGION_SIDE_BITS);
+    let c = position_indices.map(|c| c as usize & REGION_SIDE_MASK);
+    (
+        r,
+        (c.z * REGION_SIDE_CHUNKS + c.y) * REGION_SIDE_CHUNKS + c.x,
+    )
+}
+
+/// Saves and loads chunks in region files in a directory. Chunks can be
+/// loaded on other threads while a chunk is saved, because saving only
+/// changes the table entry of that chunk and space that no other chunk
+/// uses. A chunk must not be loaded while it is being saved.
+pub struct RegionStore {
+    pub root: PathBuf,
+}
+
+impl RegionStore {
+    pub fn new(root: PathBuf) -> Self {
+        RegionStore { root }
+    }
+
+    fn region_path(&self, region_indices: Vector3<i32>) -> PathBuf {
+        self.root.join(format!(
+            "r.{}.{}.{}.bin",
+            region_indices.x, region_indices.y, region_indices.z
+        ))
+    }
+
+    fn read_header(file: &mut fs::File) -> io::Result<Vec<(u32, u32)>> {
+        let mut header = vec![0; REGION_HEADER_BYTES];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        let mut reader = Reader::new(&header);
+        if reader.read_bytes(4)? != REGION_MAGIC {
+            return invalid_data(String::from("Not a region file."));
+        }
+        let version = reader.read_u16()?;
+        if version != REGION_FORMAT_VERSION {
+            return invalid_data(format!("Unsupported region format version {}.", version));
+        }
+        (0..REGION_TOTAL_CHUNKS)
+            .map(|_| Ok((reader.read_u32()?, reader.read_u32()?)))
+            .collect()
+    }
+
+    /// Finds where to write `length` bytes for the chunk at `index` given
+    /// the region table. The old copy of the chunk counts as free space.
+    fn find_space(table: &[(u32, u32)], index: usize, length: usize) -> usize {
+        let mut used: Vec<(usize, usize)> = table
+            .iter()
+            .enumerate()
+            .filter(|&(i, &(offset, _))| i != index && offset != 0)
+            .map(|(_, &(offset, length))| (offset as usize, offset as usize + length as usize))
+            .collect();
+        used.sort();
+        let mut position = REGION_HEADER_BYTES;
+        for (start, end) in used {
+            if start >= position + length {
+                break;
+            }
+            position = position.max(end);
+        }
+        position
+    }
+
+    /// Loads a chunk. Returns `None` if the chunk was never saved.
+    pub fn load(
+        &self,
+        position_indices: Vector3<i32>,
+        registry: &BlockRegistry,
+    ) -> io::Result<Option<Chunk>> {
+        let (region_indices, index) = split_chunk_position(position_indices);
+        let mut file = match fs::File::open(self.region_path(region_indices)) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let (offset, length) = Self::read_header(&mut file)?[index];
+        if offset == 0 {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut bytes)?;
+        decode_chunk(position_indices, &bytes, registry).map(Some)
+    }
+
+    pub fn save(&self, chunk: &Chunk, registry: &BlockRegistry) -> io::Result<()> {
+        let (region_indices, index) = split_chunk_position(chunk.position_indices);
+        fs::create_dir_all(&self.root)?;
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(self.region_path(region_indices))?;
+
+        let mut table = if file.metadata()?.len() == 0 {
+            let mut header = Vec::with_capacity(REGION_HEADER_BYTES);
+            header.extend_from_slice(REGION_MAGIC);
+            write_u16(&mut header, REGION_FORMAT_VERSION);
+            header.resize(REGION_HEADER_BYTES, 0);
+            file.write_all(&header)?;
+            vec![(0, 0); REGION_TOTAL_CHUNKS]
+        } else {
+            Self::read_header(&mut file)?
+        };
+
+        let bytes = encode_chunk(chunk, registry);
+        let offset = Self::find_space(&table, index, bytes.len());
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.write_all(&bytes)?;
+
+        let mut entry = Vec::with_capacity(8);
+        write_u32(&mut entry, offset as u32);
+        write_u32(&mut entry, bytes.len() as u32);
+        file.seek(SeekFrom::Start((4 + 2 + index * 8) as u64))?;
+        file.write_all(&entry)?;
+
+        // Drop the space after the last chunk, which is left over when a
+        // chunk moved into a gap.
+        table[index] = (offset as u32, bytes.len() as u32);
+        let end = table
+            .iter()
+            .map(|&(offset, length)| offset as usize + length as usize)
+            .fold(REGION_HEADER_BYTES, usize::max);
+        file.set_len(end as u64)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noise;
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::parse(
+            "[stone]\ntextures = stone\n[dirt]\ntextures = dirt\n[log]\ntextures = log",
+            |_| true,
+        )
+        .unwrap()
+    }
+
+    fn random_chunk(position_indices: Vector3<i32>) -> Chunk {
+        let mut chunk = Chunk::new(position_indices);
+        for i in 0..CHUNK_TOTAL_BLOCKS {
+            let h = noise::hash(1, i as i32, 0, 0);
+            let id = (h % 4) as usize;
+            let state = if id == 0 { 0 } else { (h >> 8) as u8 % 3 };
+            chunk.blocks.set(i, Block::new(id, state));
+        }
+        for (i, biome) in chunk.biomes.iter_mut().enumerate() {
+            *biome = Biome::ALL[i % BIOME_COUNT];
+        }
+        chunk
+    }
+
+    fn assert_round_trips(chunk: &Chunk) -> usize {
+        let registry = registry();
+        let bytes = encode_chunk(chunk, &registry);
+        let decoded = decode_chunk(chunk.position_indices, &bytes, &registry).unwrap();
+        assert!(decoded.blocks == chunk.blocks);
+        assert!(decoded.biomes[..] == chunk.biomes[..]);
+        bytes.len()
+    }
+
+    #[test]
+    fn round_trips_empty_chunk() {
+        let size = assert_round_trips(&Chunk::new(Vector3::zero()));
+        assert!(size < 300);
+    }
+
+    #[test]
+    fn round_trips_full_chunk() {
+        let mut chunk = Chunk::new(Vector3::zero());
+        chunk.blocks.fill(Block(1));
+        let size = assert_round_trips(&chunk);
+        assert!(size < 300);
+    }
+
+    #[test]
+    fn round_trips_random_chunk() {
+        assert_round_trips(&random_chunk(Vector3::zero()));
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_truncated_data() {
+        let registry = registry();
+        let mut bytes = encode_chunk(&random_chunk(Vector3::zero()), &registry);
+        assert!(decode_chunk(Vector3::zero(), &bytes[..bytes.len() - 1], &registry).is_err());
+        bytes[0] = 99;
+        assert!(decode_chunk(Vector3::zero(), &bytes, &registry).is_err());
+    }
+
+    #[test]
+    fn splits_negative_chunk_positions() {
+        assert_eq!(
+            split_chunk_position(Vector3::new(-1, 0, 8)),
+            (Vector3::new(-1, 0, 1), REGION_SIDE_CHUNKS - 1)
+        );
+    }
+
+    #[test]
+    fn saves_and_loads_chunks_in_region_files() {
+        let registry = registry();
+        let root =
+            ::std::env::temp_dir().join(format!("blocks-storage-test-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let store = RegionStore::new(root.clone());
+
+        let a = random_chunk(Vector3::new(-1, 2, 3));
+        let b = Chunk::new(Vector3::new(-2, 2, 3));
+        store.save(&a, &registry).unwrap();
+        store.save(&b, &registry).unwrap();
+        assert!(store
+            .load(Vector3::new(5, 2, 3), &registry)
+            .unwrap()
+            .is_none());
+        assert!(store
+            .load(Vector3::new(100, 0, 0), &registry)
+            .unwrap()
+            .is_none());
+
+        // Overwrite a chunk.
+        let mut c = random_chunk(Vector3::new(-1, 2, 3));
+        c.blocks.set(7, Block(3));
+        store.save(&c, &registry).unwrap();
+
+        let loaded = store.load(c.position_indices, &registry).unwrap().unwrap();
+        assert!(loaded.blocks == c.blocks);
+        let loaded = store.load(b.position_indices, &registry).unwrap().unwrap();
+        assert!(loaded.blocks == b.blocks);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reuses_space_of_chunks_that_grew() {
+        let registry = registry();
+        let root = ::std::env::temp_dir().join(format!(
+            "blocks-storage-growth-test-{}",
+            ::std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let store = RegionStore::new(root.clone());
+        let file_len = || {
+            fs::read_dir(&root)
+                .unwrap()
+                .map(|entry| entry.unwrap().metadata().unwrap().len() as usize)
+                .sum::<usize>()
+        };
+
+        let a = random_chunk(Vector3::new(0, 0, 0));
+        let mut b = Chunk::new(Vector3::new(1, 0, 0));
+        let mut c = Chunk::new(Vector3::new(2, 0, 0));
+        store.save(&a, &registry).unwrap();
+        store.save(&b, &registry).unwrap();
+        store.save(&c, &registry).unwrap();
+        for step in 0..50 {
+            // Every save makes the chunk larger than its old copy.
+            for i in 0..20 {
+                let h = noise::hash(2, step, i, 0);
+                b.blocks.set(
+                    h as usize % CHUNK_TOTAL_BLOCKS,
+                    Block::new(1 + (h >> 16) as usize % 3, 0),
+                );
+            }
+            store.save(&b, &registry).unwrap();
+            if step % 10 == 0 {
+                c.blocks.set(step as usize, Block(1));
+                store.save(&c, &registry).unwrap();
+            }
+            let size = |chunk: &Chunk| encode_chunk(chunk, &registry).len();
+            // Old copies do not pile up in the file.
+            assert!(file_len() <= REGION_HEADER_BYTES + size(&a) + 2 * size(&b) + size(&c));
+        }
+
+        for chunk in [&a, &b, &c].iter() {
+            let loaded = store
+                .load(chunk.position_indices, &registry)
+                .unwrap()
+                .unwrap();
+            assert!(loaded.blocks == chunk.blocks);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
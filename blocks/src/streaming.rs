@@ -0,0 +1,204 @@
+use cgmath::*;
+use std::collections::HashSet;
+use world::World;
+
+#[inline]
+fn length2(v: Vector3<i32>) -> i32 {
+    v.x * v.x + v.y * v.y + v.z * v.z
+}
+
+/// Decides which chunks to load and unload as the camera moves. Chunks
+/// within `load_radius` chunks of the camera are loaded, nearest first and
+/// chunks in view before the others. Chunks further away than
+/// `unload_radius` are unloaded. Keeping the unload radius larger than the
+/// load radius prevents chunks from being loaded and unloaded over and over
+/// when the camera moves back and forth over a chunk border.
+pub struct ChunkStreamer {
+    pub load_radius: i32,
+    pub unload_radius: i32,
+    /// Maximum number of chunks to load per update.
+    pub loads_per_update: usize,
+    center: Option<Vector3<i32>>,
+    /// Chunks within the load radius that are not loaded yet.
+    pending: Vec<Vector3<i32>>,
+    /// Chunks handed out for loading that are not in the world yet because
+    /// they are still being generated.
+    requested: HashSet<Vector3<i32>>,
+}
+
+/// What to do with the world after an update.
+pub struct StreamingUpdate {
+    pub load: Vec<Vector3<i32>>,
+    pub unload: Vec<Vector3<i32>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StreamingStats {
+    pub loaded: usize,
+    pub pending: usize,
+}
+
+impl ChunkStreamer {
+    pub fn new(load_radius: i32, unload_radius: i32, loads_per_update: usize) -> Self {
+        assert!(load_radius <= unload_radius);
+        ChunkStreamer {
+            load_radius,
+            unload_radius,
+            loads_per_update,
+            center: None,
+            pending: Vec::new(),
+            requested: HashSet::new(),
+        }
+    }
+
+    /// Plans the next chunks to load and unload around the chunk at
+    /// `center`. Chunks to load do not have to be inserted into the world
+    /// right away. Chunks to unload may not have been inserted yet, in which
+    /// case their loading should be cancelled.
+    pub fn update<F>(&mut self, world: &World, center: Vector3<i32>, in_view: F) -> StreamingUpdate
+    where
+        F: Fn(Vector3<i32>) -> bool,
+    {
+        let mut unload = Vec::new();
+
+        self.requested.retain(|&p| world.chunk_at(p).is_none());
+
+        if self.center != Some(center) {
+            self.center = Some(center);
+
+            let r = self.load_radius;
+            self.pending.clear();
+            for z in -r..=r {
+                for y in -r..=r {
+                    for x in -r..=r {
+                        let offset = Vector3::new(x, y, z);
+                        let p = center + offset;
+                        if length2(offset) <= r * r
+                            && world.chunk_at(p).is_none()
+                            && !self.requested.contains(&p)
+                        {
+                            self.pending.push(p);
+                        }
+                    }
+                }
+            }
+
+            let r = self.unload_radius;
+            let is_far = |p: Vector3<i32>| length2(p - center) > r * r;
+            unload.extend(
+                world
+                    .chunks()
+                    .map(|chunk| chunk.position_indices)
+                    .filter(|&p| is_far(p)),
+            );
+            unload.extend(self.requested.iter().cloned().filter(|&p| is_far(p)));
+            self.requested.retain(|&p| !is_far(p));
+        }
+
+        // The view changes all the time so sort on every update. Sorting in
+        // reverse allows taking chunks from the end.
+        self.pending.sort_by_key(|&p| {
+            let offset = p - center;
+            ::std::cmp::Reverse((!in_view(p), length2(offset)))
+        });
+        let n = self.pending.len().saturating_sub(self.loads_per_update);
+        let mut load = self.pending.split_off(n);
+        load.reverse();
+        self.requested.extend(load.iter().cloned());
+
+        StreamingUpdate { load, unload }
+    }
+
+    /// Makes the next update recompute which chunks to load, for example
+    /// after changing the radii.
+    pub fn reset(&mut self) {
+        self.center = None;
+    }
+
+    pub fn stats(&self, world: &World) -> StreamingStats {
+        StreamingStats {
+            loaded: world.chunk_count(),
+            pending: self.pending.len() + self.requested.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::Chunk;
+
+    fn apply(world: &mut World, update: &StreamingUpdate) {
+        for &p in update.load.iter() {
+            world.insert_chunk(Chunk::new(p));
+        }
+        for &p in update.unload.iter() {
+            world.remove_chunk(p);
+        }
+    }
+
+    #[test]
+    fn loads_nearest_chunks_in_view_first() {
+        let world = World::new();
+        let mut streamer = ChunkStreamer::new(2, 3, 3);
+        let update = streamer.update(&world, Vector3::zero(), |p| p.x >= 0);
+        assert_eq!(update.load[0], Vector3::zero());
+        for &p in update.load.iter() {
+            assert!(p.x >= 0);
+            assert!(length2(p) <= 1);
+        }
+        assert!(update.unload.is_empty());
+        assert_eq!(streamer.stats(&world).pending, 33);
+    }
+
+    #[test]
+    fn does_not_request_chunks_twice() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(1, 1, 100);
+        let first = streamer.update(&world, Vector3::zero(), |_| true);
+        assert_eq!(first.load.len(), 7);
+
+        // The chunks have not arrived yet when the camera moves.
+        let update = streamer.update(&world, Vector3::new(1, 0, 0), |_| true);
+        assert_eq!(update.load.len(), 5);
+        let mut unload = update.unload.clone();
+        unload.sort_by_key(|p| (p.x, p.y, p.z));
+        assert_eq!(
+            unload,
+            vec![
+                Vector3::new(-1, 0, 0),
+                Vector3::new(0, -1, 0),
+                Vector3::new(0, 0, -1),
+                Vector3::new(0, 0, 1),
+                Vector3::new(0, 1, 0),
+            ]
+        );
+
+        apply(&mut world, &first);
+        let update = streamer.update(&world, Vector3::new(1, 0, 0), |_| true);
+        assert!(update.load.is_empty());
+        assert_eq!(streamer.stats(&world).pending, 5);
+    }
+
+    #[test]
+    fn unloads_chunks_outside_the_unload_radius() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(1, 2, 100);
+        let update = streamer.update(&world, Vector3::zero(), |_| true);
+        apply(&mut world, &update);
+        assert_eq!(world.chunk_count(), 7);
+
+        // Moving one chunk keeps everything within the unload radius.
+        let update = streamer.update(&world, Vector3::new(1, 0, 0), |_| true);
+        assert!(update.unload.is_empty());
+        assert_eq!(update.load.len(), 5);
+        apply(&mut world, &update);
+
+        let update = streamer.update(&world, Vector3::new(3, 0, 0), |_| true);
+        assert_eq!(update.unload.len(), 10);
+        assert!(update
+            .unload
+            .iter()
+            .all(|&p| length2(p - Vector3::new(3, 0, 0)) > 4));
+    }
+}
@@ -0,0 +1,355 @@
+use block::Block;
+use block_registry::BlockRegistry;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_SIDE_BITS;
+use chunk::CHUNK_SIDE_BLOCKS;
+use noise;
+use terrain::TerrainGenerator;
+use world::split_block_position;
+
+/// Blocks the structures are built from, looked up by name in the registry.
+#[derive(Clone, Copy, Debug)]
+pub struct StructureBlocks {
+    pub cobblestone: Block,
+    pub planks: Block,
+    pub log: Block,
+    pub leaves: Block,
+}
+
+impl StructureBlocks {
+    pub fn from_registry(registry: &BlockRegistry) -> Result<Self, String> {
+        let block = |name: &str| {
+            registry
+                .block(name)
+                .ok_or_else(|| format!("Structure generation requires a {:?} block.", name))
+        };
+        Ok(StructureBlocks {
+            cobblestone: block("cobblestone")?,
+            planks: block("planks_oak")?,
+            log: block("log_oak")?,
+            leaves: block("leaves_oak")?,
+        })
+    }
+}
+
+/// Structures extend at most this many blocks horizontally from their
+/// anchor, so they only reach into the neighbouring chunk columns.
+const MAX_REACH: i32 = 2;
+
+/// Blocks relative to the anchor of a structure, which is the first empty
+/// block above the surface.
+pub struct Template {
+    pub blocks: Vec<(Vector3<i32>, Block)>,
+}
+
+impl Template {
+    fn new() -> Self {
+        Template { blocks: Vec::new() }
+    }
+
+    /// Adds a block, replacing the block at the same position if any.
+    fn set(&mut self, p: Vector3<i32>, block: Block) {
+        match self.blocks.iter_mut().find(|&&mut (q, _)| q == p) {
+            Some(entry) => entry.1 = block,
+            None => self.blocks.push((p, block)),
+        }
+    }
+
+    /// An upright log with a roughly round crown of leaves.
+    pub fn tree(blocks: &StructureBlocks, height: i32, random: u32) -> Self {
+        let mut t = Template::new();
+        for dy in -2..2 {
+            let radius = if dy < 1 { 2 } else { 1 };
+            for dz in -radius..=radius {
+                for dx in -radius..=radius {
+                    // Randomly skip some of the corners.
+                    let corner = dx * dx + dz * dz == 2 * radius * radius;
+                    if corner && noise::hash(random, dx, dy, dz) & 1 == 0 {
+                        continue;
+                    }
+                    t.set(Vector3::new(dx, height + dy, dz), blocks.leaves);
+                }
+            }
+        }
+        for y in 0..height {
+            t.set(Vector3::new(0, y, 0), blocks.log);
+        }
+        t
+    }
+
+    /// A lump of cobblestone sunk into the ground.
+    pub fn boulder(blocks: &StructureBlocks, radius: i32) -> Self {
+        let mut t = Template::new();
+        for dz in -radius..=radius {
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if dx * dx + dy * dy + dz * dz <= radius * radius + 1 {
+                        t.set(Vector3::new(dx, dy, dz), blocks.cobblestone);
+                    }
+                }
+            }
+        }
+        t
+    }
+
+    /// Four plank walls with logs in the corners, a doorway and a flat roof.
+    pub fn hut(blocks: &StructureBlocks) -> Self {
+        const R: i32 = 2;
+        const H: i32 = 3;
+        let mut t = Template::new();
+        for y in -1..=H {
+            for z in -R..=R {
+                for x in -R..=R {
+                    let wall = x.abs() == R || z.abs() == R;
+                    let corner = x.abs() == R && z.abs() == R;
+                    let floor_or_roof = y == -1 || y == H;
+                    let doorway = z == -R && x == 0 && (y == 0 || y == 1);
+                    if corner && !floor_or_roof {
+                        t.set(Vector3::new(x, y, z), blocks.log);
+                    } else if (wall || floor_or_roof) && !doorway {
+                        t.set(Vector3::new(x, y, z), blocks.planks);
+                    }
+                }
+            }
+        }
+        t
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum StructureKind {
+    Tree,
+    Boulder,
+    Hut,
+}
+
+/// The structures each chunk column may get, with the chance out of 256
+/// for each before scaling by the density for the biome.
+const ATTEMPTS: [(StructureKind, u32); 5] = [
+    (StructureKind::Tree, 96),
+    (StructureKind::Tree, 96),
+    (StructureKind::Tree, 96),
+    (StructureKind::Boulder, 24),
+    (StructureKind::Hut, 4),
+];
+
+/// Places structures on top of the terrain. Structures belong to the chunk
+/// column containing their anchor and may extend into neighbouring chunks.
+/// Every chunk places the parts of the structures from its own and the
+/// neighbouring columns that fall into it while it is generated, so no
+/// state is kept between chunks and chunks that already exist are never
+/// changed.
+///
+/// Structures only fill blocks that the terrain leaves empty. Where
+/// structures overlap, the block with the highest ID wins. This makes the
+/// result independent of the order in which chunks are generated.
+pub struct StructureGenerator {
+    seed: u32,
+    blocks: StructureBlocks,
+}
+
+impl StructureGenerator {
+    pub fn new(seed: u32, blocks: StructureBlocks) -> Self {
+        StructureGenerator { seed, blocks }
+    }
+
+    /// Finds the anchor of a structure at a random position in the chunk
+    /// column at (cx, cz). Returns `None` if a cave opens up there.
+    fn anchor(&self, terrain: &TerrainGenerator, cx: i32, cz: i32, i: u32) -> Option<Vector3<i32>> {
+        let h = noise::hash(self.seed, cx, i as i32, cz);
+        let x = cx * CHUNK_SIDE_BLOCKS as i32 + (h & 0xff) as i32 % CHUNK_SIDE_BLOCKS as i32;
+        let z = cz * CHUNK_SIDE_BLOCKS as i32 + (h >> 8 & 0xff) as i32 % CHUNK_SIDE_BLOCKS as i32;
+        let y = terrain.surface_at(x, z);
+        if terrain.block_at(Vector3::new(x, y, z)) != Block::VOID {
+            Some(Vector3::new(x, y + 1, z))
+        } else {
+            None
+        }
+    }
+
+    /// The blocks in world space of the structures anchored in the chunk
+    /// column at (cx, cz).
+    fn structures_in(
+        &self,
+        terrain: &TerrainGenerator,
+        cx: i32,
+        cz: i32,
+    ) -> Vec<(Vector3<i32>, Block)> {
+        let mut blocks = Vec::new();
+
+        for (attempt, &(kind, chance)) in ATTEMPTS.iter().enumerate() {
+            let anchor = match self.anchor(terrain, cx, cz, attempt as u32) {
+                Some(anchor) => anchor,
+                None => continue,
+            };
+            let biome = terrain.biome_at(anchor.x, anchor.z).params();
+            let density = match kind {
+                StructureKind::Tree => biome.tree_density,
+                StructureKind::Boulder => biome.boulder_density,
+                StructureKind::Hut => 1.0,
+            };
+            let r = noise::hash(self.seed ^ 0x2545_f491, cx, attempt as i32, cz);
+            if (r & 0xff) as f32 >= chance as f32 * density {
+                continue;
+            }
+            let r = r >> 8;
+            let template = match kind {
+                StructureKind::Tree => Template::tree(&self.blocks, 4 + (r % 3) as i32, r),
+                StructureKind::Boulder => Template::boulder(&self.blocks, 1 + (r % 2) as i32),
+                StructureKind::Hut => Template::hut(&self.blocks),
+            };
+            blocks.extend(
+                template
+                    .blocks
+                    .iter()
+                    .map(|&(p, block)| (anchor + p, block)),
+            );
+        }
+
+        blocks
+    }
+
+    /// Places the parts of the structures anchored in the column of the
+    /// freshly generated `chunk` and in the neighbouring columns that fall
+    /// into it.
+    pub fn populate(&self, terrain: &TerrainGenerator, chunk: &mut Chunk) {
+        let position_indices = chunk.position_indices;
+        // The columns with anchors within `MAX_REACH` blocks of the chunk.
+        let columns = |c: i32| {
+            let min = c * CHUNK_SIDE_BLOCKS as i32 - MAX_REACH;
+            let max = (c + 1) * CHUNK_SIDE_BLOCKS as i32 - 1 + MAX_REACH;
+            (min >> CHUNK_SIDE_BITS)..=(max >> CHUNK_SIDE_BITS)
+        };
+        for cz in columns(position_indices.z) {
+            for cx in columns(position_indices.x) {
+                for (p, block) in self.structures_in(terrain, cx, cz) {
+                    let (chunk_indices, q) = split_block_position(p);
+                    if chunk_indices != position_indices || terrain.block_at(p) != Block::VOID {
+                        continue;
+                    }
+                    let mut current = chunk.block_at_mut(q.x, q.y, q.z);
+                    if block.id() > current.id() {
+                        *current = block;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::TerrainBlocks;
+    use world::World;
+
+    const STONE: Block = Block(1);
+    const DIRT: Block = Block(2);
+    const COBBLESTONE: Block = Block(3);
+    const PLANKS: Block = Block(4);
+    const LOG: Block = Block(5);
+    const LEAVES: Block = Block(6);
+    const SAND: Block = Block(7);
+
+    fn generators(seed: u32) -> (TerrainGenerator, StructureGenerator) {
+        (
+            TerrainGenerator::new(
+                seed,
+                TerrainBlocks {
+                    stone: STONE,
+                    dirt: DIRT,
+                    cobblestone: COBBLESTONE,
+                    sand: SAND,
+                },
+            ),
+            StructureGenerator::new(
+                seed,
+                StructureBlocks {
+                    cobblestone: COBBLESTONE,
+                    planks: PLANKS,
+                    log: LOG,
+                    leaves: LEAVES,
+                },
+            ),
+        )
+    }
+
+    fn generate(seed: u32, positions: &[Vector3<i32>]) -> World {
+        let (terrain, structures) = generators(seed);
+        let mut world = World::new();
+        for &p in positions {
+            let mut chunk = terrain.generate(p);
+            structures.populate(&terrain, &mut chunk);
+            world.insert_chunk(chunk);
+        }
+        world
+    }
+
+    fn region() -> Vec<Vector3<i32>> {
+        let mut positions = Vec::new();
+        for z in -2..2 {
+            for y in -1..2 {
+                for x in -2..2 {
+                    positions.push(Vector3::new(x, y, z));
+                }
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn templates_do_not_repeat_positions() {
+        let (_, structures) = generators(0);
+        let b = &structures.blocks;
+        for t in [
+            Template::tree(b, 5, 7),
+            Template::boulder(b, 2),
+            Template::hut(b),
+        ]
+        .iter()
+        {
+            for (i, &(p, _)) in t.blocks.iter().enumerate() {
+                assert!(t.blocks[i + 1..].iter().all(|&(q, _)| q != p));
+                assert!(p.x.abs() <= MAX_REACH && p.z.abs() <= MAX_REACH);
+            }
+        }
+    }
+
+    #[test]
+    fn result_does_not_depend_on_generation_order() {
+        let positions = region();
+        let a = generate(42, &positions);
+        let reversed: Vec<_> = positions.iter().cloned().rev().collect();
+        let b = generate(42, &reversed);
+        let mut trees = 0;
+        for &p in positions.iter() {
+            let chunk_a = a.chunk_at(p).unwrap();
+            let chunk_b = b.chunk_at(p).unwrap();
+            assert!(chunk_a.blocks == chunk_b.blocks, "{:?}", p);
+            trees += chunk_a.blocks.iter().filter(|&b| b == LOG).count();
+        }
+        assert!(trees > 0);
+    }
+
+    #[test]
+    fn places_parts_of_structures_from_neighbouring_columns() {
+        let (terrain, structures) = generators(42);
+        let mut crossing = 0;
+        for p in region() {
+            let mut chunk = terrain.generate(p);
+            structures.populate(&terrain, &mut chunk);
+            for (dx, dz) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+                for (q, block) in structures.structures_in(&terrain, p.x + dx, p.z + dz) {
+                    let (chunk_indices, r) = split_block_position(q);
+                    if chunk_indices == p && terrain.block_at(q) == Block::VOID {
+                        assert!(chunk.block_at(r.x, r.y, r.z).id() >= block.id());
+                        crossing += 1;
+                    }
+                }
+            }
+        }
+        // Some structures near the edge of their column stick out of it.
+        assert!(crossing > 0);
+    }
+}
@@ -0,0 +1,418 @@
+use biome;
+use biome::Biome;
+use biome::BiomeMap;
+use block::Block;
+use block_registry::BlockRegistry;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_SIDE_BLOCKS;
+use noise;
+use noise::Noise;
+
+/// Horizontal size of hills in blocks.
+const HEIGHT_SCALE: f32 = 96.0;
+/// Size of overhangs and floating bits in blocks.
+const DENSITY_SCALE: f32 = 24.0;
+/// How many blocks the density noise can move the surface.
+const DENSITY_AMPLITUDE: f32 = 8.0;
+/// Number of surface blocks, like dirt or sand, on top of the stone.
+const DIRT_DEPTH: usize = 3;
+/// Size of cave bends in blocks.
+const CAVE_SCALE: f32 = 48.0;
+/// Tunnels are where two noise fields are both close to zero. Larger
+/// values give wider tunnels.
+const CAVE_RADIUS: f32 = 0.06;
+/// Caves stay this many blocks below the height map so they rarely break
+/// through the surface.
+const CAVE_SURFACE_MARGIN: f32 = 6.0;
+
+/// A block scattered through the stone in blobs. Blobs are most common
+/// halfway between `min_y` and `max_y` and absent outside that range.
+#[derive(Clone, Copy, Debug)]
+pub struct Ore {
+    pub block: Block,
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Size of the blobs in blocks.
+    pub scale: f32,
+    /// Noise value above which the ore replaces stone where it is most
+    /// common. Higher values give fewer and smaller blobs.
+    pub threshold: f32,
+}
+
+/// Blocks the generator places, looked up by name in the registry.
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainBlocks {
+    pub stone: Block,
+    pub dirt: Block,
+    pub cobblestone: Block,
+    pub sand: Block,
+}
+
+impl TerrainBlocks {
+    pub fn from_registry(registry: &BlockRegistry) -> Result<Self, String> {
+        let block = |name: &str| {
+            registry
+                .block(name)
+                .ok_or_else(|| format!("Terrain generation requires a {:?} block.", name))
+        };
+        Ok(TerrainBlocks {
+            stone: block("stone")?,
+            dirt: block("dirt")?,
+            cobblestone: block("cobblestone")?,
+            sand: block("sand")?,
+        })
+    }
+}
+
+/// Column properties that do not depend on the height.
+#[derive(Clone, Copy, Debug)]
+struct Column {
+    /// Height of the surface before adding the density noise.
+    height: f32,
+    biome: Biome,
+}
+
+/// Fills chunks from a seed. The content of a block only depends on the
+/// seed and its world position, so chunks can be generated in any order
+/// and regenerated at will.
+pub struct TerrainGenerator {
+    blocks: TerrainBlocks,
+    ores: Vec<(Ore, Noise)>,
+    biomes: BiomeMap,
+    height_noise: Noise,
+    density_noise: Noise,
+    cave_noise: [Noise; 2],
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32, blocks: TerrainBlocks) -> Self {
+        let ores = vec![
+            Ore {
+                block: blocks.dirt,
+                min_y: -48,
+                max_y: 8,
+                scale: 8.0,
+                threshold: 0.35,
+            },
+            Ore {
+                block: blocks.cobblestone,
+                min_y: -96,
+                max_y: -8,
+                scale: 4.0,
+                threshold: 0.4,
+            },
+        ];
+        TerrainGenerator {
+            blocks,
+            ores: ores
+                .into_iter()
+                .enumerate()
+                .map(|(i, ore)| (ore, Noise::new(noise::hash(seed, i as i32, 0, 1))))
+                .collect(),
+            biomes: BiomeMap::new(seed),
+            height_noise: Noise::new(seed),
+            density_noise: Noise::new(seed ^ 0x5bd1_e995),
+            cave_noise: [
+                Noise::new(noise::hash(seed, 0, 0, 2)),
+                Noise::new(noise::hash(seed, 1, 0, 2)),
+            ],
+        }
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        self.biomes.biome_at(x, z)
+    }
+
+    fn column_at(&self, x: i32, z: i32) -> Column {
+        let weights = self.biomes.weights_at(x, z);
+        let h = self
+            .height_noise
+            .fractal2(x as f32 / HEIGHT_SCALE, z as f32 / HEIGHT_SCALE, 4);
+        Column {
+            height: biome::blend(&weights, |p| p.base_height)
+                + h * biome::blend(&weights, |p| p.height_amplitude),
+            biome: self.biomes.biome_at(x, z),
+        }
+    }
+
+    /// Whether the block at (x, y, z) is part of the ground. Positive
+    /// density is solid. The height map dominates the density far from the
+    /// surface so the density noise only carves overhangs near it.
+    fn is_ground(&self, height: f32, x: i32, y: i32, z: i32) -> bool {
+        let d = self.density_noise.fractal3(
+            x as f32 / DENSITY_SCALE,
+            y as f32 / DENSITY_SCALE,
+            z as f32 / DENSITY_SCALE,
+            3,
+        );
+        height - y as f32 + d * DENSITY_AMPLITUDE > 0.0
+    }
+
+    /// The y coordinate of the highest ground block in column (x, z).
+    pub fn surface_at(&self, x: i32, z: i32) -> i32 {
+        let height = self.column_at(x, z).height;
+        let top = (height + DENSITY_AMPLITUDE).ceil() as i32;
+        let bottom = (height - DENSITY_AMPLITUDE).floor() as i32;
+        (bottom..=top)
+            .rev()
+            .find(|&y| self.is_ground(height, x, y, z))
+            .unwrap_or(bottom)
+    }
+
+    /// Whether the block at (x, y, z) is carved out by a tunnel.
+    fn is_cave(&self, height: f32, x: i32, y: i32, z: i32) -> bool {
+        if y as f32 > height - CAVE_SURFACE_MARGIN {
+            return false;
+        }
+        // Squash vertically so tunnels run mostly horizontal.
+        let (x, y, z) = (
+            x as f32 / CAVE_SCALE,
+            y as f32 * 2.0 / CAVE_SCALE,
+            z as f32 / CAVE_SCALE,
+        );
+        let a = self.cave_noise[0].fractal3(x, y, z, 2);
+        let b = self.cave_noise[1].fractal3(x, y, z, 2);
+        a * a + b * b < CAVE_RADIUS * CAVE_RADIUS
+    }
+
+    /// The ore replacing the stone at (x, y, z), if any.
+    fn ore_at(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        for &(ref ore, ref noise) in self.ores.iter() {
+            if y < ore.min_y || y >= ore.max_y {
+                continue;
+            }
+            // The threshold rises linearly to 1 towards the ends of the
+            // range, where the noise never gets.
+            let half = (ore.max_y - ore.min_y) as f32 / 2.0;
+            let t = ((y - ore.min_y) as f32 - half).abs() / half;
+            let n = noise.sample3(
+                x as f32 / ore.scale,
+                y as f32 / ore.scale,
+                z as f32 / ore.scale,
+            );
+            if n > ore.threshold + (1.0 - ore.threshold) * t {
+                return Some(ore.block);
+            }
+        }
+        None
+    }
+
+    /// The block at (x, y, z) given its column and the number of ground
+    /// blocks directly above it.
+    fn block_at_depth(&self, column: &Column, x: i32, y: i32, z: i32, depth: usize) -> Block {
+        if self.is_cave(column.height, x, y, z) {
+            Block::VOID
+        } else if depth < DIRT_DEPTH {
+            match column.biome {
+                Biome::Plains | Biome::Forest => self.blocks.dirt,
+                Biome::Desert => self.blocks.sand,
+                Biome::Mountains => self.blocks.stone,
+            }
+        } else {
+            self.ore_at(x, y, z).unwrap_or(self.blocks.stone)
+        }
+    }
+
+    /// Generates a single block. Slow, but `generate` produces the same
+    /// blocks.
+    pub fn block_at(&self, p: Vector3<i32>) -> Block {
+        let column = self.column_at(p.x, p.z);
+        if !self.is_ground(column.height, p.x, p.y, p.z) {
+            return Block::VOID;
+        }
+        let depth = (1..=DIRT_DEPTH as i32)
+            .take_while(|&dy| self.is_ground(column.height, p.x, p.y + dy, p.z))
+            .count();
+        self.block_at_depth(&column, p.x, p.y, p.z, depth)
+    }
+
+    pub fn generate(&self, position_indices: Vector3<i32>) -> Chunk {
+        const SIDE: usize = CHUNK_SIDE_BLOCKS;
+        let mut chunk = Chunk::new(position_indices);
+        let origin = position_indices * SIDE as i32;
+
+        for z in 0..SIDE {
+            for x in 0..SIDE {
+                let wx = origin.x + x as i32;
+                let wz = origin.z + z as i32;
+                let column = self.column_at(wx, wz);
+                *chunk.biome_at_mut(x, z) = column.biome;
+
+                // Walk down from above the chunk, counting the ground
+                // blocks above each block to decide where dirt goes.
+                let mut depth = 0;
+                for y in (0..SIDE + DIRT_DEPTH).rev() {
+                    let wy = origin.y + y as i32;
+                    if !self.is_ground(column.height, wx, wy, wz) {
+                        depth = 0;
+                        continue;
+                    }
+                    if y < SIDE {
+                        *chunk.block_at_mut(x, y, z) =
+                            self.block_at_depth(&column, wx, wy, wz, depth);
+                    }
+                    depth += 1;
+                }
+            }
+        }
+
+        // Chunks far above or below the surface become uniform.
+        chunk.blocks.compact();
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::CHUNK_TOTAL_BLOCKS;
+
+    const STONE: Block = Block(1);
+    const DIRT: Block = Block(2);
+    const COBBLESTONE: Block = Block(3);
+    const SAND: Block = Block(4);
+
+    fn generator(seed: u32) -> TerrainGenerator {
+        TerrainGenerator::new(
+            seed,
+            TerrainBlocks {
+                stone: STONE,
+                dirt: DIRT,
+                cobblestone: COBBLESTONE,
+                sand: SAND,
+            },
+        )
+    }
+
+    /// FNV-1a so the hashes do not depend on the standard library version.
+    fn chunk_hash(chunk: &Chunk) -> u64 {
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for block in chunk.blocks.iter() {
+            for &byte in [block.0 as u8, (block.0 >> 8) as u8].iter() {
+                h ^= byte as u64;
+                h = h.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        h
+    }
+
+    fn count(chunk: &Chunk, block: Block) -> usize {
+        chunk.blocks.iter().filter(|&b| b == block).count()
+    }
+
+    #[test]
+    fn generates_the_same_chunks_for_the_same_seed() {
+        let positions = [
+            Vector3::new(0, 0, 0),
+            Vector3::new(-1, 0, 3),
+            Vector3::new(2, -1, -2),
+        ];
+        let a = generator(42);
+        let hashes: Vec<u64> = positions
+            .iter()
+            .map(|&p| chunk_hash(&a.generate(p)))
+            .collect();
+        // Generate in reverse order with a new generator.
+        let b = generator(42);
+        for (&p, &h) in positions.iter().zip(hashes.iter()).rev() {
+            assert_eq!(chunk_hash(&b.generate(p)), h);
+        }
+        // Changes when the generator output changes on purpose.
+        assert_eq!(
+            hashes,
+            [
+                0xce11_cb75_c48d_58ab,
+                0xaa58_467a_2366_d21d,
+                0x7a4a_2789_3222_7c86
+            ]
+        );
+    }
+
+    #[test]
+    fn generates_different_chunks_for_different_seeds() {
+        let p = Vector3::new(0, 0, 0);
+        assert_ne!(
+            chunk_hash(&generator(1).generate(p)),
+            chunk_hash(&generator(2).generate(p))
+        );
+    }
+
+    #[test]
+    fn fills_deep_chunks_and_empties_high_chunks() {
+        let g = generator(42);
+        let deep = g.generate(Vector3::new(0, -8, 0));
+        // Only caves are empty this deep.
+        assert!(count(&deep, STONE) > CHUNK_TOTAL_BLOCKS / 2);
+        assert_eq!(count(&deep, DIRT), 0);
+        let high = g.generate(Vector3::new(0, 4, 0));
+        assert_eq!(count(&high, Block::VOID), CHUNK_TOTAL_BLOCKS);
+    }
+
+    #[test]
+    fn covers_stone_with_surface_blocks() {
+        let g = generator(42);
+        for cx in -2..2 {
+            for cy in -1..1 {
+                let chunk = g.generate(Vector3::new(cx, cy, 0));
+                for z in 0..CHUNK_SIDE_BLOCKS {
+                    for y in 0..CHUNK_SIDE_BLOCKS - 1 {
+                        for x in 0..CHUNK_SIDE_BLOCKS {
+                            if *chunk.block_at(x, y + 1, z) == Block::VOID {
+                                let below = *chunk.block_at(x, y, z);
+                                let surface = match chunk.biome_at(x, z) {
+                                    Biome::Plains | Biome::Forest => DIRT,
+                                    Biome::Desert => SAND,
+                                    Biome::Mountains => STONE,
+                                };
+                                assert!(below == Block::VOID || below == surface);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_match_single_blocks_across_borders() {
+        // Every block only depends on its world position, so tunnels and
+        // ore blobs line up no matter in which order chunks are generated.
+        let g = generator(42);
+        for &p in [Vector3::new(0, -2, 0), Vector3::new(-1, -2, 0)].iter() {
+            let chunk = g.generate(p);
+            let origin = p * CHUNK_SIDE_BLOCKS as i32;
+            for z in 0..CHUNK_SIDE_BLOCKS {
+                for y in 0..CHUNK_SIDE_BLOCKS {
+                    for x in 0..CHUNK_SIDE_BLOCKS {
+                        let q = origin + Vector3::new(x, y, z).cast::<i32>().unwrap();
+                        assert_eq!(*chunk.block_at(x, y, z), g.block_at(q), "{:?}", q);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn carves_caves_and_scatters_ores_underground() {
+        let g = generator(42);
+        let mut void = 0;
+        let mut cobblestone_y = Vec::new();
+        for cz in -2..2 {
+            for cy in -5..-2 {
+                for cx in -2..2 {
+                    let chunk = g.generate(Vector3::new(cx, cy, cz));
+                    void += count(&chunk, Block::VOID);
+                    if count(&chunk, COBBLESTONE) > 0 {
+                        cobblestone_y.push(cy);
+                    }
+                }
+            }
+        }
+        assert!(void > 0);
+        assert!(!cobblestone_y.is_empty());
+        // No ores outside their depth range.
+        let high = g.generate(Vector3::new(0, 1, 0));
+        assert_eq!(count(&high, COBBLESTONE), 0);
+    }
+}
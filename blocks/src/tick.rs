@@ -0,0 +1,321 @@
+use block_registry::BlockRegistry;
+use cgmath::*;
+use chunk::CHUNK_SIDE_BITS;
+use chunk::CHUNK_SIDE_BLOCKS;
+use chunk::CHUNK_SIDE_MASK;
+use chunk::CHUNK_TOTAL_BLOCKS;
+use cube::Face;
+use noise;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use world::split_block_position;
+use world::World;
+
+/// Number of blocks picked for a random tick in every loaded chunk per
+/// tick.
+pub const RANDOM_TICKS_PER_CHUNK: usize = 3;
+
+/// What a block does when it is ticked at a position.
+pub type TickCallback = fn(&mut TickContext, Vector3<i32>);
+
+/// The tick callbacks of a block type.
+#[derive(Clone, Copy, Default)]
+pub struct BlockTicks {
+    /// Called for blocks picked at random, on average once every
+    /// `CHUNK_TOTAL_BLOCKS / RANDOM_TICKS_PER_CHUNK` ticks.
+    pub random: Option<TickCallback>,
+    /// Called for the ticks scheduled with `TickContext::schedule`.
+    pub scheduled: Option<TickCallback>,
+}
+
+/// What a tick callback can look at and change.
+pub struct TickContext<'a> {
+    pub world: &'a mut World,
+    pub registry: &'a BlockRegistry,
+    scheduler: &'a mut TickScheduler,
+}
+
+impl<'a> TickContext<'a> {
+    /// Ticks the block at `position` again after `delay` ticks.
+    pub fn schedule(&mut self, position: Vector3<i32>, delay: u64) {
+        self.scheduler.schedule(position, delay);
+    }
+
+    /// Ticks the neighbours of a block the callback changed.
+    pub fn block_changed(&mut self, position: Vector3<i32>) {
+        self.scheduler.block_changed(position);
+    }
+
+    /// A random number below `n`.
+    pub fn random(&mut self, n: u32) -> u32 {
+        self.scheduler.next_random() % n
+    }
+}
+
+/// Drives block behaviour over time. Every tick first runs the scheduled
+/// ticks that are due and then picks random blocks in every loaded chunk
+/// that contains a block type with a random tick callback. Chunks are
+/// visited in a fixed order and the random numbers only depend on the seed
+/// and the tick, so the same world always changes the same way.
+///
+/// Scheduled ticks of blocks in chunks that are not loaded when they are
+/// due are dropped.
+pub struct TickScheduler {
+    seed: u32,
+    tick: u64,
+    /// Positions to tick, by the tick at which they are due.
+    scheduled: BTreeMap<u64, Vec<Vector3<i32>>>,
+    /// Positions in `scheduled`, which are only scheduled once.
+    pending: HashSet<Vector3<i32>>,
+    /// Random numbers taken during this tick.
+    random_count: u32,
+}
+
+impl TickScheduler {
+    pub fn new(seed: u32) -> Self {
+        TickScheduler {
+            seed,
+            tick: 0,
+            scheduled: BTreeMap::new(),
+            pending: HashSet::new(),
+            random_count: 0,
+        }
+    }
+
+    /// Number of ticks so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Number of scheduled ticks that are not due yet.
+    pub fn scheduled_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Ticks the block at `position` after `delay` ticks, unless it is
+    /// already scheduled. A delay of 0 ticks it during the next tick.
+    pub fn schedule(&mut self, position: Vector3<i32>, delay: u64) {
+        if self.pending.insert(position) {
+            let due = self.tick + delay.max(1);
+            self.scheduled
+                .entry(due)
+                .or_insert_with(Vec::new)
+                .push(position);
+        }
+    }
+
+    /// Ticks the block at `position` and its neighbours during the next
+    /// tick, so they can react to it changing.
+    pub fn block_changed(&mut self, position: Vector3<i32>) {
+        self.schedule(position, 1);
+        for &face in Face::ALL.iter() {
+            self.schedule(position + face.normal(), 1);
+        }
+    }
+
+    fn next_random(&mut self) -> u32 {
+        self.random_count += 1;
+        noise::hash(
+            self.seed,
+            self.tick as i32,
+            (self.tick >> 32) as i32,
+            self.random_count as i32,
+        )
+    }
+
+    pub fn update(&mut self, world: &mut World, registry: &BlockRegistry) {
+        self.tick += 1;
+        self.random_count = 0;
+
+        if let Some(due) = self.scheduled.remove(&self.tick) {
+            for position in due {
+                self.pending.remove(&position);
+                if world.chunk_at(split_block_position(position).0).is_none() {
+                    continue;
+                }
+                if let Some(callback) = registry.ticks(world.block_at(position)).scheduled {
+                    let mut context = TickContext {
+                        world: &mut *world,
+                        registry,
+                        scheduler: &mut *self,
+                    };
+                    callback(&mut context, position);
+                }
+            }
+        }
+
+        let mut chunks: Vec<Vector3<i32>> = world
+            .chunks()
+            .filter(|chunk| {
+                chunk
+                    .blocks
+                    .palette()
+                    .iter()
+                    .any(|&block| registry.ticks(block).random.is_some())
+            })
+            .map(|chunk| chunk.position_indices)
+            .collect();
+        chunks.sort_by_key(|p| (p.x, p.y, p.z));
+
+        for position_indices in chunks {
+            for _ in 0..RANDOM_TICKS_PER_CHUNK {
+                let index = self.next_random() as usize % CHUNK_TOTAL_BLOCKS;
+                let offset = Vector3::new(
+                    index & CHUNK_SIDE_MASK,
+                    (index >> CHUNK_SIDE_BITS) & CHUNK_SIDE_MASK,
+                    index >> (2 * CHUNK_SIDE_BITS),
+                );
+                let position =
+                    position_indices * CHUNK_SIDE_BLOCKS as i32 + offset.cast::<i32>().unwrap();
+                if let Some(callback) = registry.ticks(world.block_at(position)).random {
+                    let mut context = TickContext {
+                        world: &mut *world,
+                        registry,
+                        scheduler: &mut *self,
+                    };
+                    callback(&mut context, position);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use chunk::Chunk;
+
+    const STONE: Block = Block(1);
+    const COUNTER: Block = Block(2);
+    const REPEATER: Block = Block(3);
+
+    /// Counts its ticks in its state.
+    fn count(context: &mut TickContext, position: Vector3<i32>) {
+        let block = context.world.block_at(position);
+        context
+            .world
+            .set_block(position, block.with_state(block.state() + 1));
+    }
+
+    /// Counts its ticks and ticks again 2 ticks later, up to 5 times.
+    fn repeat(context: &mut TickContext, position: Vector3<i32>) {
+        count(context, position);
+        if context.world.block_at(position).state() < 5 {
+            context.schedule(position, 2);
+        }
+    }
+
+    fn turn_to_stone(context: &mut TickContext, position: Vector3<i32>) {
+        context.world.set_block(position, STONE);
+    }
+
+    fn registry() -> BlockRegistry {
+        let mut registry =
+            BlockRegistry::parse("[stone]\n[counter]\n[repeater]", |_| true).unwrap();
+        registry.set_ticks(
+            COUNTER,
+            BlockTicks {
+                random: Some(turn_to_stone),
+                scheduled: Some(count),
+            },
+        );
+        registry.set_ticks(
+            REPEATER,
+            BlockTicks {
+                random: None,
+                scheduled: Some(repeat),
+            },
+        );
+        registry
+    }
+
+    fn world_with(block: Block) -> World {
+        let mut world = World::new();
+        world.insert_chunk(Chunk::new(Vector3::zero()));
+        world.set_block(Vector3::new(1, 2, 3), block);
+        world
+    }
+
+    #[test]
+    fn scheduled_ticks_run_once_after_their_delay() {
+        let registry = registry();
+        let mut world = world_with(COUNTER);
+        let mut ticks = TickScheduler::new(0);
+        let p = Vector3::new(1, 2, 3);
+        ticks.schedule(p, 3);
+        // Already scheduled.
+        ticks.schedule(p, 1);
+        assert_eq!(ticks.scheduled_count(), 1);
+
+        ticks.update(&mut world, &registry);
+        ticks.update(&mut world, &registry);
+        assert_eq!(world.block_at(p).state(), 0);
+        ticks.update(&mut world, &registry);
+        assert_eq!(world.block_at(p).state(), 1);
+        assert_eq!(ticks.scheduled_count(), 0);
+        for _ in 0..10 {
+            ticks.update(&mut world, &registry);
+        }
+        assert_eq!(world.block_at(p).state(), 1);
+    }
+
+    #[test]
+    fn callbacks_schedule_more_ticks() {
+        let registry = registry();
+        let mut world = world_with(REPEATER);
+        let mut ticks = TickScheduler::new(0);
+        let p = Vector3::new(1, 2, 3);
+        ticks.schedule(p, 1);
+        for _ in 0..8 {
+            ticks.update(&mut world, &registry);
+        }
+        assert_eq!(world.block_at(p).state(), 4);
+        for _ in 0..10 {
+            ticks.update(&mut world, &registry);
+        }
+        assert_eq!(world.block_at(p).state(), 5);
+        assert_eq!(ticks.scheduled_count(), 0);
+    }
+
+    #[test]
+    fn scheduled_ticks_in_unloaded_chunks_are_dropped() {
+        let registry = registry();
+        let mut world = world_with(COUNTER);
+        let mut ticks = TickScheduler::new(0);
+        let p = Vector3::new(17, 2, 3);
+        ticks.schedule(p, 1);
+        ticks.update(&mut world, &registry);
+        assert_eq!(ticks.scheduled_count(), 0);
+
+        world.insert_chunk(Chunk::new(Vector3::unit_x()));
+        world.set_block(p, COUNTER);
+        for _ in 0..10 {
+            ticks.update(&mut world, &registry);
+        }
+        assert_eq!(world.block_at(p), COUNTER);
+    }
+
+    #[test]
+    fn random_ticks_are_spread_out_and_deterministic() {
+        let registry = registry();
+        let run = |seed: u32| {
+            let mut world = World::new();
+            let mut chunk = Chunk::new(Vector3::zero());
+            chunk.blocks.fill(COUNTER);
+            world.insert_chunk(chunk);
+            let mut ticks = TickScheduler::new(seed);
+            for _ in 0..100 {
+                ticks.update(&mut world, &registry);
+            }
+            let chunk = world.chunk_at(Vector3::zero()).unwrap();
+            chunk.blocks.iter().collect::<Vec<_>>()
+        };
+        let blocks = run(1);
+        let stone_count = blocks.iter().filter(|&&b| b == STONE).count();
+        // Blocks picked twice only turn to stone once.
+        assert!(stone_count > 250 && stone_count <= 100 * RANDOM_TICKS_PER_CHUNK);
+        assert_eq!(blocks, run(1));
+        assert_ne!(blocks, run(2));
+    }
+}
@@ -0,0 +1,290 @@
+use biome::Biome;
+use block::Block;
+use cgmath::*;
+use chunk::Chunk;
+use chunk::CHUNK_SIDE_BITS;
+use chunk::CHUNK_SIDE_MASK;
+use cube::Face;
+use falling::FallingBlock;
+use light::Light;
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::mem;
+
+/// Splits a world space block position into the indices of the chunk
+/// containing it and the position of the block within that chunk.
+#[inline]
+pub fn split_block_position(position: Vector3<i32>) -> (Vector3<i32>, Vector3<usize>) {
+    // NOTE: Arithmetic shifts round towards negative infinity so
+    // negative positions end up in the right chunk.
+    (
+        Vector3 {
+            x: position.x >> CHUNK_SIDE_BITS,
+            y: position.y >> CHUNK_SIDE_BITS,
+            z: position.z >> CHUNK_SIDE_BITS,
+        },
+        Vector3 {
+            x: position.x as usize & CHUNK_SIDE_MASK,
+            y: position.y as usize & CHUNK_SIDE_MASK,
+            z: position.z as usize & CHUNK_SIDE_MASK,
+        },
+    )
+}
+
+pub struct World {
+    chunks: HashMap<Vector3<i32>, Chunk>,
+    /// Chunks that need to be meshed again.
+    dirty_chunks: HashSet<Vector3<i32>>,
+    /// Chunks that differ from their copy on disk.
+    unsaved_chunks: HashSet<Vector3<i32>>,
+    /// Inserted chunks whose light has not been computed yet.
+    unlit_chunks: Vec<Vector3<i32>>,
+    /// Positions of blocks that changed since their light was updated.
+    block_changes: Vec<Vector3<i32>>,
+    /// Blocks that are falling and not part of a chunk.
+    falling_blocks: Vec<FallingBlock>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            chunks: HashMap::new(),
+            dirty_chunks: HashSet::new(),
+            unsaved_chunks: HashSet::new(),
+            unlit_chunks: Vec::new(),
+            block_changes: Vec::new(),
+            falling_blocks: Vec::new(),
+        }
+    }
+
+    /// Marks a chunk and its direct neighbours as changed, used when a
+    /// chunk is added or removed.
+    fn mark_chunk_and_neighbours_dirty(&mut self, position_indices: Vector3<i32>) {
+        self.dirty_chunks.insert(position_indices);
+        for &face in Face::ALL.iter() {
+            self.dirty_chunks.insert(position_indices + face.normal());
+        }
+    }
+
+    /// Marks the chunk containing a block as changed, along with any
+    /// neighbouring chunk that shares a face with the block.
+    fn mark_block_dirty(&mut self, position: Vector3<i32>) {
+        let (chunk_indices, p) = split_block_position(position);
+        self.dirty_chunks.insert(chunk_indices);
+        for axis in 0..3 {
+            if p[axis] == 0 {
+                let mut n = chunk_indices;
+                n[axis] -= 1;
+                self.dirty_chunks.insert(n);
+            }
+            if p[axis] == CHUNK_SIDE_MASK {
+                let mut n = chunk_indices;
+                n[axis] += 1;
+                self.dirty_chunks.insert(n);
+            }
+        }
+    }
+
+    /// Forces all loaded chunks to be reported as changed, for example
+    /// when the way they are meshed changes.
+    pub fn mark_all_chunks_dirty(&mut self) {
+        self.dirty_chunks.extend(self.chunks.keys().cloned());
+    }
+
+    /// Returns the indices of all chunks whose contents or neighbours
+    /// changed since the last call. Some of them may no longer be loaded.
+    pub fn take_dirty_chunks(&mut self) -> HashSet<Vector3<i32>> {
+        mem::replace(&mut self.dirty_chunks, HashSet::new())
+    }
+
+    /// Marks a chunk as differing from its copy on disk. Modifying blocks
+    /// does this automatically but inserted chunks are assumed to come from
+    /// disk.
+    pub fn mark_chunk_unsaved(&mut self, position_indices: Vector3<i32>) {
+        self.unsaved_chunks.insert(position_indices);
+    }
+
+    /// Returns the indices of all chunks that changed since the last call
+    /// and need to be saved. Some of them may no longer be loaded.
+    pub fn take_unsaved_chunks(&mut self) -> HashSet<Vector3<i32>> {
+        mem::replace(&mut self.unsaved_chunks, HashSet::new())
+    }
+
+    /// Clears the unsaved mark of a single chunk and returns whether it was
+    /// set.
+    pub fn take_unsaved_chunk(&mut self, position_indices: Vector3<i32>) -> bool {
+        self.unsaved_chunks.remove(&position_indices)
+    }
+
+    /// Returns the indices of the chunks inserted since the last call. Some
+    /// of them may no longer be loaded.
+    pub fn take_unlit_chunks(&mut self) -> Vec<Vector3<i32>> {
+        mem::replace(&mut self.unlit_chunks, Vec::new())
+    }
+
+    /// Returns the positions of the blocks set since the last call.
+    pub fn take_block_changes(&mut self) -> Vec<Vector3<i32>> {
+        mem::replace(&mut self.block_changes, Vec::new())
+    }
+
+    pub fn falling_blocks(&self) -> &[FallingBlock] {
+        &self.falling_blocks
+    }
+
+    pub fn add_falling_block(&mut self, falling_block: FallingBlock) {
+        self.falling_blocks.push(falling_block);
+    }
+
+    /// Removes all falling blocks from the world and returns them.
+    pub fn take_falling_blocks(&mut self) -> Vec<FallingBlock> {
+        mem::replace(&mut self.falling_blocks, Vec::new())
+    }
+
+    pub fn chunk_at(&self, position_indices: Vector3<i32>) -> Option<&Chunk> {
+        self.chunks.get(&position_indices)
+    }
+
+    pub fn chunk_at_mut(&mut self, position_indices: Vector3<i32>) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&position_indices)
+    }
+
+    /// Returns the chunk at the given indices, inserting an empty chunk
+    /// if it is not loaded.
+    pub fn chunk_at_or_insert(&mut self, position_indices: Vector3<i32>) -> &mut Chunk {
+        if !self.chunks.contains_key(&position_indices) {
+            self.mark_chunk_and_neighbours_dirty(position_indices);
+            self.unlit_chunks.push(position_indices);
+        }
+        self.chunks
+            .entry(position_indices)
+            .or_insert_with(|| Chunk::new(position_indices))
+    }
+
+    /// Inserts a chunk at its own position indices, returning the chunk
+    /// that was previously stored there.
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        self.mark_chunk_and_neighbours_dirty(chunk.position_indices);
+        self.unlit_chunks.push(chunk.position_indices);
+        self.chunks.insert(chunk.position_indices, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, position_indices: Vector3<i32>) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&position_indices);
+        if chunk.is_some() {
+            self.mark_chunk_and_neighbours_dirty(position_indices);
+        }
+        chunk
+    }
+
+    pub fn chunks(&self) -> hash_map::Values<Vector3<i32>, Chunk> {
+        self.chunks.values()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Blocks in chunks that are not loaded are reported as void.
+    pub fn block_at(&self, position: Vector3<i32>) -> Block {
+        let (chunk_indices, p) = split_block_position(position);
+        match self.chunks.get(&chunk_indices) {
+            Some(chunk) => *chunk.block_at(p.x, p.y, p.z),
+            None => Block::VOID,
+        }
+    }
+
+    /// The light at a block, if its chunk is loaded.
+    pub fn light_at(&self, position: Vector3<i32>) -> Option<Light> {
+        let (chunk_indices, p) = split_block_position(position);
+        self.chunks
+            .get(&chunk_indices)
+            .map(|chunk| chunk.light_at(p.x, p.y, p.z))
+    }
+
+    /// Sets the light at a block if its chunk is loaded. Chunks showing the
+    /// block are marked dirty when the light changes.
+    pub fn set_light(&mut self, position: Vector3<i32>, light: Light) {
+        let (chunk_indices, p) = split_block_position(position);
+        let changed = match self.chunks.get_mut(&chunk_indices) {
+            Some(chunk) => {
+                let current = chunk.light_at_mut(p.x, p.y, p.z);
+                let changed = *current != light;
+                *current = light;
+                changed
+            }
+            None => false,
+        };
+        if changed {
+            self.mark_block_dirty(position);
+        }
+    }
+
+    /// The biome of the column containing `position`, if its chunk is
+    /// loaded.
+    pub fn biome_at(&self, position: Vector3<i32>) -> Option<Biome> {
+        let (chunk_indices, p) = split_block_position(position);
+        self.chunks
+            .get(&chunk_indices)
+            .map(|chunk| chunk.biome_at(p.x, p.z))
+    }
+
+    /// Setting a block in a chunk that is not loaded creates that chunk.
+    pub fn set_block(&mut self, position: Vector3<i32>, block: Block) {
+        let (chunk_indices, p) = split_block_position(position);
+        *self
+            .chunk_at_or_insert(chunk_indices)
+            .block_at_mut(p.x, p.y, p.z) = block;
+        self.mark_block_dirty(position);
+        self.unsaved_chunks.insert(chunk_indices);
+        self.block_changes.push(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: Block = Block(1);
+    const DIRT: Block = Block(2);
+
+    #[test]
+    fn split_negative_positions() {
+        assert_eq!(
+            split_block_position(Vector3::new(-1, 16, 17)),
+            (Vector3::new(-1, 1, 1), Vector3::new(15, 0, 1))
+        );
+        assert_eq!(
+            split_block_position(Vector3::new(-16, -17, 0)),
+            (Vector3::new(-1, -2, 0), Vector3::new(0, 15, 0))
+        );
+    }
+
+    #[test]
+    fn set_block_across_chunk_boundaries() {
+        let mut world = World::new();
+        world.set_block(Vector3::new(15, 0, 0), STONE);
+        world.set_block(Vector3::new(16, 0, 0), DIRT);
+        world.set_block(Vector3::new(-1, 0, 0), DIRT);
+        assert_eq!(world.chunk_count(), 3);
+        assert_eq!(world.block_at(Vector3::new(15, 0, 0)), STONE);
+        assert_eq!(world.block_at(Vector3::new(16, 0, 0)), DIRT);
+        assert_eq!(world.block_at(Vector3::new(-1, 0, 0)), DIRT);
+        assert_eq!(world.block_at(Vector3::new(-100, 0, 0)), Block::VOID);
+        assert!(world.take_dirty_chunks().contains(&Vector3::new(1, 0, 0)));
+        assert!(world.take_dirty_chunks().is_empty());
+        world.set_block(Vector3::new(15, 3, 3), Block::VOID);
+        let dirty = world.take_dirty_chunks();
+        assert_eq!(dirty.len(), 2);
+        assert!(dirty.contains(&Vector3::new(0, 0, 0)));
+        assert!(dirty.contains(&Vector3::new(1, 0, 0)));
+        assert_eq!(world.take_unsaved_chunks().len(), 3);
+        assert_eq!(
+            *world
+                .chunk_at(Vector3::new(-1, 0, 0))
+                .unwrap()
+                .block_at(15, 0, 0),
+            DIRT
+        );
+    }
+}